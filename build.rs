@@ -0,0 +1,600 @@
+//! Generates the profile types and messages from the FIT Profile export in
+//! `profile/`, with the layout of the Types and Messages sheets of the
+//! `Profile.xlsx` shipped with the FIT SDK.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Rust names of profile types, when they differ from `Fit{Name}Type`.
+const TYPE_NAMES: &[(&str, &str)] = &[
+    ("mesg_num", "FitGlobalMessageType"),
+    ("manufacturer", "FitFileManufacturerType"),
+    ("wkt_step_duration", "FitWorkoutStepDuration"),
+    ("wkt_step_target", "FitWorkoutStepTarget"),
+];
+
+/// Types that aren't enums in the profile, but whose values are.
+const ENUM_TYPES: &[&str] = &[
+    "mesg_num",
+    "manufacturer",
+    "battery_status",
+    "fit_base_unit",
+];
+
+const BASE_TYPES: &[&str] = &[
+    "enum", "sint8", "uint8", "sint16", "uint16", "sint32", "uint32", "string", "float32",
+    "float64", "uint8z", "uint16z", "uint32z", "byte", "sint64", "uint64", "uint64z",
+];
+
+struct Type {
+    name: String,
+    base_type: String,
+    values: Vec<(String, String, String)>,
+}
+
+struct Field {
+    number: Option<u8>,
+    name: String,
+    field_type: String,
+    scale: f64,
+    offset: i32,
+    units: String,
+    comment: String,
+}
+
+struct Message {
+    name: String,
+    section: Option<String>,
+    fields: Vec<Field>,
+}
+
+fn main() {
+    let dir = Path::new("profile");
+    println!("cargo:rerun-if-changed=profile");
+
+    let types = parse_types(&read_csv(&dir.join("Types.csv")));
+    let messages = parse_messages(&read_csv(&dir.join("Messages.csv")));
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out.join("types.rs"), generate_types(&types)).unwrap();
+    fs::write(out.join("profile.rs"), generate_messages(&messages, &types)).unwrap();
+}
+
+/// Rows of a CSV file, without its header row.
+fn read_csv(path: &Path) -> Vec<Vec<String>> {
+    let content =
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("can't read {}: {e}", path.display()));
+
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => row.push(std::mem::take(&mut cell)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut cell));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => cell.push(c),
+        }
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    }
+    rows.into_iter().skip(1).collect()
+}
+
+fn column(row: &[String], n: usize) -> &str {
+    row.get(n).map(|c| c.trim()).unwrap_or_default()
+}
+
+fn parse_types(rows: &[Vec<String>]) -> Vec<Type> {
+    let mut types: Vec<Type> = Vec::new();
+    for row in rows {
+        let (name, base_type) = (column(row, 0), column(row, 1));
+        let (value_name, value) = (column(row, 2), column(row, 3));
+        if !name.is_empty() {
+            types.push(Type {
+                name: name.to_string(),
+                base_type: base_type.to_string(),
+                values: Vec::new(),
+            });
+        } else if !value_name.is_empty() {
+            let ty = types.last_mut().expect("type value before any type");
+            ty.values.push((
+                value_name.to_string(),
+                value.to_string(),
+                column(row, 4).to_string(),
+            ));
+        }
+    }
+    types
+}
+
+fn parse_messages(rows: &[Vec<String>]) -> Vec<Message> {
+    let mut messages: Vec<Message> = Vec::new();
+    let mut section = None;
+    for row in rows {
+        let name = column(row, 0);
+        if !name.is_empty() {
+            // Sections are upper case rows between messages.
+            if name.chars().any(|c| c.is_ascii_lowercase()) {
+                messages.push(Message {
+                    name: name.to_string(),
+                    section: section.take(),
+                    fields: Vec::new(),
+                });
+            } else {
+                section = Some(name.to_string());
+            }
+            continue;
+        }
+
+        let field_name = column(row, 2);
+        if field_name.is_empty() {
+            continue;
+        }
+        let message = messages.last_mut().expect("field before any message");
+        let number = column(row, 1);
+        let context = format!("{}.{field_name}", message.name);
+        let scales: Vec<f64> = numbers(column(row, 6), &context);
+        let offsets: Vec<i32> = numbers(column(row, 7), &context);
+        let units = list(column(row, 8));
+        if let Some(scale) = scales.iter().find(|s| !s.is_finite() || **s <= 0.0) {
+            panic!("{context}: scale {scale}");
+        }
+
+        // Fields with several components list a scale, offset and units per
+        // component, the field itself holds the packed raw components. A
+        // single component is the field itself, rescaled.
+        let components = list(column(row, 5));
+        let (scale, offset, units, mut comment) = if components.len() <= 1 {
+            if scales.len() > 1 || offsets.len() > 1 || units.len() > 1 {
+                panic!("{context}: several scales, offsets or units without components");
+            }
+            let scale = scales.first().copied().unwrap_or(1.0);
+            let offset = offsets.first().copied().unwrap_or(0);
+            let units = units.first().copied().unwrap_or_default();
+            (scale, offset, units.to_string(), String::new())
+        } else {
+            let per_component = |n: usize| n <= 1 || n == components.len();
+            if !per_component(scales.len())
+                || !per_component(offsets.len())
+                || !per_component(units.len())
+            {
+                panic!("{context}: scales, offsets or units don't match its components");
+            }
+            let described: Vec<String> = components
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    let scale = scales.get(i).or(scales.first()).copied().unwrap_or(1.0);
+                    let offset = offsets.get(i).or(offsets.first()).copied().unwrap_or(0);
+                    match units.get(i).or(units.first()).copied().unwrap_or_default() {
+                        "" => format!("`{name}` (scale {scale}, offset {offset})"),
+                        units => format!("`{name}` (scale {scale}, offset {offset}, {units})"),
+                    }
+                })
+                .collect();
+            let comment = format!("Components: {}.", described.join(", "));
+            (1.0, 0, String::new(), comment)
+        };
+        let profile_comment = column(row, 13);
+        if !profile_comment.is_empty() {
+            comment = match comment.is_empty() {
+                true => profile_comment.to_string(),
+                false => format!("{profile_comment} {comment}"),
+            };
+        }
+
+        message.fields.push(Field {
+            number: (!number.is_empty()).then(|| {
+                number
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{context}: bad field number {number:?}"))
+            }),
+            name: field_name.to_string(),
+            field_type: column(row, 3).to_string(),
+            scale,
+            offset,
+            units,
+            comment,
+        });
+    }
+    messages
+}
+
+/// Values of a comma separated cell, empty for an empty cell.
+fn list(cell: &str) -> Vec<&str> {
+    if cell.is_empty() {
+        return Vec::new();
+    }
+    cell.split(',').map(str::trim).collect()
+}
+
+/// Numbers of a scale or offset cell. Panics on a value that isn't a
+/// number, rather than generating wrong physical values.
+fn numbers<T: std::str::FromStr>(cell: &str, context: &str) -> Vec<T> {
+    list(cell)
+        .into_iter()
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("{context}: can't parse scale or offset {value:?}"))
+        })
+        .collect()
+}
+
+/// CamelCase of a snake_case profile name.
+fn camel(name: &str) -> String {
+    const DIGITS: [&str; 10] = [
+        "Zero", "One", "Two", "Three", "Four", "Five", "Six", "Seven", "Eight", "Nine",
+    ];
+
+    let mut camel = String::new();
+    for part in name.split('_').filter(|p| !p.is_empty()) {
+        let mut chars = part.chars();
+        let mut first = chars.next().unwrap();
+        // Identifiers can't start with a digit, spell it out as a word.
+        if let (Some(digit), true) = (first.to_digit(10), camel.is_empty()) {
+            camel.push_str(DIGITS[digit as usize]);
+            match chars.next() {
+                Some(next) => first = next,
+                None => continue,
+            }
+        }
+        camel.extend(first.to_uppercase());
+        camel.extend(chars.flat_map(|c| c.to_lowercase()));
+    }
+    camel
+}
+
+fn type_name(name: &str) -> String {
+    TYPE_NAMES
+        .iter()
+        .find(|(profile, _)| *profile == name)
+        .map(|(_, rust)| rust.to_string())
+        .unwrap_or_else(|| format!("Fit{}Type", camel(name)))
+}
+
+fn integer_type(base_type: &str) -> &'static str {
+    match base_type {
+        "enum" | "uint8" | "uint8z" => "u8",
+        "uint16" | "uint16z" => "u16",
+        "uint32" | "uint32z" => "u32",
+        _ => panic!("no integer type for base type {base_type}"),
+    }
+}
+
+/// Base type of a field, resolving profile types to their base type.
+fn base_type<'a>(field_type: &'a str, types: &'a [Type]) -> &'a str {
+    if BASE_TYPES.contains(&field_type) {
+        return field_type;
+    }
+    match types.iter().find(|t| t.name == field_type) {
+        Some(ty) => &ty.base_type,
+        None => panic!("unknown field type {field_type}"),
+    }
+}
+
+/// Integer of a type value, written in decimal or hex in the profile.
+fn number(value: &str) -> u64 {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .unwrap_or_else(|_| panic!("bad type value {value:?}"))
+}
+
+fn doc(out: &mut String, indent: &str, comment: &str) {
+    if !comment.is_empty() {
+        writeln!(out, "{indent}/// {comment}").unwrap();
+    }
+}
+
+fn generate_types(types: &[Type]) -> String {
+    let mut out = String::from("// Generated by build.rs from profile/Types.csv.\n");
+    for ty in types {
+        if ty.values.is_empty()
+            || (ty.base_type != "enum" && !ENUM_TYPES.contains(&ty.name.as_str()))
+        {
+            continue;
+        }
+        let name = type_name(&ty.name);
+
+        if ty.name == "manufacturer" {
+            writeln!(out, "\nfit_manufacturers! {{").unwrap();
+            for (value_name, value, _) in &ty.values {
+                writeln!(out, "    {} = {value}, {value_name:?};", camel(value_name)).unwrap();
+            }
+            writeln!(out, "}}").unwrap();
+            continue;
+        }
+
+        // A value listed twice keeps its first name, later ones are aliases.
+        let mut values: Vec<(&str, &str, String)> = Vec::new();
+        for (value_name, value, comment) in &ty.values {
+            match values
+                .iter_mut()
+                .find(|(_, v, _)| number(v) == number(value))
+            {
+                Some((_, _, doc)) => {
+                    let alias = format!("Also `{value_name}` in the profile.");
+                    *doc = match doc.trim_end_matches('.') {
+                        "" => alias,
+                        comment => format!("{comment}. {alias}"),
+                    };
+                }
+                None => values.push((value_name, value, comment.clone())),
+            }
+        }
+
+        let int = integer_type(&ty.base_type);
+        writeln!(out, "\n/// `{}` type of the FIT Profile.", ty.name).unwrap();
+        writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq)]").unwrap();
+        writeln!(out, "pub enum {name} {{").unwrap();
+        for (value_name, value, comment) in &values {
+            doc(&mut out, "    ", comment);
+            writeln!(out, "    {} = {value},", camel(value_name)).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+
+        writeln!(out, "\nimpl {name} {{").unwrap();
+        writeln!(out, "    /// Value name as written in the FIT Profile.").unwrap();
+        writeln!(out, "    pub fn name(&self) -> &'static str {{").unwrap();
+        writeln!(out, "        match self {{").unwrap();
+        for (value_name, _, _) in &values {
+            writeln!(
+                out,
+                "            Self::{} => {value_name:?},",
+                camel(value_name)
+            )
+            .unwrap();
+        }
+        writeln!(out, "        }}\n    }}\n}}").unwrap();
+
+        writeln!(out, "\nimpl TryFrom<{int}> for {name} {{").unwrap();
+        writeln!(out, "    type Error = {int};\n").unwrap();
+        writeln!(
+            out,
+            "    fn try_from(value: {int}) -> Result<Self, Self::Error> {{"
+        )
+        .unwrap();
+        writeln!(out, "        match value {{").unwrap();
+        for (value_name, value, _) in &values {
+            writeln!(
+                out,
+                "            {value} => Ok(Self::{}),",
+                camel(value_name)
+            )
+            .unwrap();
+        }
+        writeln!(out, "            _ => Err(value),").unwrap();
+        writeln!(out, "        }}\n    }}\n}}").unwrap();
+    }
+    out
+}
+
+fn generate_messages(messages: &[Message], types: &[Type]) -> String {
+    let mut out = String::from("// Generated by build.rs from profile/Messages.csv.\n");
+
+    // Messages by their global message number.
+    let mesg_num = types.iter().find(|t| t.name == "mesg_num").unwrap();
+    let numbers: BTreeMap<&str, &str> = mesg_num
+        .values
+        .iter()
+        .map(|(name, value, _)| (name.as_str(), value.as_str()))
+        .collect();
+
+    writeln!(
+        out,
+        "\n/// Name of a field of a global message, as written in the FIT Profile."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub fn field_name(global_msg_num: &FitGlobalMessageType, field_number: u8) -> Option<&'static str> {{"
+    )
+    .unwrap();
+    writeln!(out, "    match global_msg_num {{").unwrap();
+    for message in messages.iter().filter(|m| !m.fields.is_empty()) {
+        assert!(
+            numbers.contains_key(message.name.as_str()),
+            "message {} isn't in mesg_num",
+            message.name
+        );
+        writeln!(
+            out,
+            "        FitGlobalMessageType::{} => Fit{}FieldDefinition::from_field_number(field_number).map(|f| f.name()),",
+            camel(&message.name),
+            camel(&message.name)
+        )
+        .unwrap();
+    }
+    writeln!(out, "        _ => None,\n    }}\n}}").unwrap();
+
+    // Units, scales and physical values, dispatched the same way as the names.
+    for (doc, signature, body) in [
+        (
+            "Units of a field of a global message, as written in the FIT Profile.",
+            "field_units(global_msg_num: &FitGlobalMessageType, field_number: u8) -> Option<&'static str>",
+            "from_field_number(field_number).map(|f| f.units())",
+        ),
+        (
+            "Scale of a field of a global message, as written in the FIT Profile.",
+            "field_scale(global_msg_num: &FitGlobalMessageType, field_number: u8) -> Option<f64>",
+            "from_field_number(field_number).map(|f| f.scale())",
+        ),
+        (
+            "Physical value of a field of a global message, see [`FitFieldDefinition::physical`].",
+            "field_physical(global_msg_num: &FitGlobalMessageType, field_number: u8, value: &FitValue) -> Option<f64>",
+            "from_field_number(field_number)?.physical(value)",
+        ),
+    ] {
+        writeln!(out, "\n/// {doc}\npub fn {signature} {{").unwrap();
+        writeln!(out, "    match global_msg_num {{").unwrap();
+        for message in messages.iter().filter(|m| !m.fields.is_empty()) {
+            writeln!(
+                out,
+                "        FitGlobalMessageType::{} => Fit{}FieldDefinition::{body},",
+                camel(&message.name),
+                camel(&message.name)
+            )
+            .unwrap();
+        }
+        writeln!(out, "        _ => None,\n    }}\n}}").unwrap();
+    }
+
+    for message in messages.iter().filter(|m| !m.fields.is_empty()) {
+        if let Some(section) = &message.section {
+            let rule = "/".repeat(section.len() + 12);
+            writeln!(out, "\n{rule}\n/////  {section}  /////\n{rule}").unwrap();
+        }
+
+        let name = format!("Fit{}FieldDefinition", camel(&message.name));
+        writeln!(out, "\n/// Fields of the `{}` message.", message.name).unwrap();
+        writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq)]").unwrap();
+        writeln!(out, "pub enum {name} {{").unwrap();
+        for field in &message.fields {
+            doc(&mut out, "    ", &field.comment);
+            writeln!(out, "    {},", camel(&field.name)).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+
+        // Subfields share the number of the main field before them.
+        let mut number = 0;
+        let fields: Vec<(&Field, u8)> = message
+            .fields
+            .iter()
+            .map(|field| {
+                number = field.number.unwrap_or(number);
+                (field, number)
+            })
+            .collect();
+
+        writeln!(out, "\nimpl FitFieldDefinition for {name} {{").unwrap();
+        writeln!(out, "    fn base_type(&self) -> FitBaseType {{").unwrap();
+        writeln!(out, "        match self {{").unwrap();
+        for (field, _) in &fields {
+            writeln!(
+                out,
+                "            Self::{} => FitBaseType::{},",
+                camel(&field.name),
+                camel(base_type(&field.field_type, types))
+            )
+            .unwrap();
+        }
+        writeln!(out, "        }}\n    }}\n").unwrap();
+
+        writeln!(out, "    fn field_number(&self) -> u8 {{").unwrap();
+        writeln!(out, "        match self {{").unwrap();
+        for (field, number) in &fields {
+            writeln!(out, "            Self::{} => {number},", camel(&field.name)).unwrap();
+        }
+        writeln!(out, "        }}\n    }}\n").unwrap();
+
+        writeln!(out, "    fn name(&self) -> &'static str {{").unwrap();
+        writeln!(out, "        match self {{").unwrap();
+        for (field, _) in &fields {
+            writeln!(
+                out,
+                "            Self::{} => {:?},",
+                camel(&field.name),
+                field.name
+            )
+            .unwrap();
+        }
+        writeln!(out, "        }}\n    }}\n").unwrap();
+
+        writeln!(
+            out,
+            "    fn from_field_number(field_number: u8) -> Option<Self> {{"
+        )
+        .unwrap();
+        writeln!(out, "        match field_number {{").unwrap();
+        for field in message.fields.iter().filter(|f| f.number.is_some()) {
+            writeln!(
+                out,
+                "            {} => Some(Self::{}),",
+                field.number.unwrap(),
+                camel(&field.name)
+            )
+            .unwrap();
+        }
+        writeln!(out, "            _ => None,\n        }}\n    }}").unwrap();
+
+        let scaled: Vec<_> = fields.iter().filter(|(f, _)| f.scale != 1.0).collect();
+        if !scaled.is_empty() {
+            writeln!(out, "\n    fn scale(&self) -> f64 {{").unwrap();
+            writeln!(out, "        match self {{").unwrap();
+            for (field, _) in &scaled {
+                writeln!(
+                    out,
+                    "            Self::{} => {:?},",
+                    camel(&field.name),
+                    field.scale
+                )
+                .unwrap();
+            }
+            if scaled.len() < fields.len() {
+                writeln!(out, "            _ => 1.0,").unwrap();
+            }
+            writeln!(out, "        }}\n    }}").unwrap();
+        }
+
+        let offset: Vec<_> = fields.iter().filter(|(f, _)| f.offset != 0).collect();
+        if !offset.is_empty() {
+            writeln!(out, "\n    fn offset(&self) -> i32 {{").unwrap();
+            writeln!(out, "        match self {{").unwrap();
+            for (field, _) in &offset {
+                writeln!(
+                    out,
+                    "            Self::{} => {},",
+                    camel(&field.name),
+                    field.offset
+                )
+                .unwrap();
+            }
+            if offset.len() < fields.len() {
+                writeln!(out, "            _ => 0,").unwrap();
+            }
+            writeln!(out, "        }}\n    }}").unwrap();
+        }
+
+        let units: Vec<_> = fields.iter().filter(|(f, _)| !f.units.is_empty()).collect();
+        if !units.is_empty() {
+            writeln!(out, "\n    fn units(&self) -> &'static str {{").unwrap();
+            writeln!(out, "        match self {{").unwrap();
+            for (field, _) in &units {
+                writeln!(
+                    out,
+                    "            Self::{} => {:?},",
+                    camel(&field.name),
+                    field.units
+                )
+                .unwrap();
+            }
+            if units.len() < fields.len() {
+                writeln!(out, "            _ => \"\",").unwrap();
+            }
+            writeln!(out, "        }}\n    }}").unwrap();
+        }
+
+        writeln!(out, "}}").unwrap();
+    }
+    out
+}
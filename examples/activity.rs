@@ -1,112 +1,79 @@
-use std::{fs::File, io::Write};
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+};
 
 use fit_no_std::{
-    FitActivityFieldDefinition, FitFile, FitFileType, FitGlobalMessageType, FitLapFieldDefinition,
-    FitRecordFieldDefinition, FitSessionFieldDefinition,
+    ActivityRecorder, ActivitySample, FitDateTime, FitEncoder, FitError, FitFileOptions,
+    FitFileType, FitPosition, FitSink, FitSportType, FitSubSportType,
 };
 
+/// Streams the activity to a file as it is recorded.
+struct FileSink(File);
+
+impl FitSink for FileSink {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), FitError> {
+        self.0.write_all(bytes).map_err(|_e| FitError::Sink)
+    }
+
+    fn patch(&mut self, offset: usize, bytes: &[u8]) -> Result<(), FitError> {
+        let patch = |file: &mut File| -> std::io::Result<()> {
+            file.seek(SeekFrom::Start(offset as u64))?;
+            file.write_all(bytes)?;
+            file.seek(SeekFrom::End(0))?;
+            Ok(())
+        };
+        patch(&mut self.0).map_err(|_e| FitError::Sink)
+    }
+}
+
 // > The required message types for an Activity file are File Id, Activity,
 // > Session, Lap, and Record messages. Device Info and Event messages are not
 // > required, but it is considered a best practice to include them.
 
 fn main() {
-    let ts: u32 = 938622559;
-    println!("Let's try to save a .fit file! Timestamp: {}", ts);
-
-    let mut fit = FitFile::<1024>::new(FitFileType::Activity, ts as u32).unwrap();
-    // Define Records [required]
-    fit.define(
-        FitGlobalMessageType::Record,
-        &[
-            FitRecordFieldDefinition::Timestamp,
-            FitRecordFieldDefinition::PositionLat,
-            FitRecordFieldDefinition::PositionLong,
-            FitRecordFieldDefinition::HeartRate,
-        ],
-    )
-    .unwrap();
-    let mut data: [u8; 13] = [0; 13];
-    data[0..4].copy_from_slice(&(ts).to_le_bytes()); // TimeStamp
-    data[4..8].copy_from_slice(&(147u32).to_le_bytes()); // Elapsed Time
-    data[8..12].copy_from_slice(&(1347u32).to_le_bytes()); // Timer Time
-    data[12] = 110; // BPM
-    fit.push(&data).unwrap();
-
-    data[0..4].copy_from_slice(&(ts + 1).to_le_bytes()); // TimeStamp
-    data[4..8].copy_from_slice(&(148u32).to_le_bytes()); // Elapsed Time
-    data[8..12].copy_from_slice(&(1348u32).to_le_bytes()); // Timer Time
-    data[12] = 115; // BPM
-    fit.push(&data).unwrap();
-
-    // Define Lap [required]
-    fit.define(
-        FitGlobalMessageType::Lap,
-        &[
-            FitLapFieldDefinition::Timestamp,
-            FitLapFieldDefinition::StartTime,
-            FitLapFieldDefinition::TotalElapsedTime,
-            FitLapFieldDefinition::TotalTimerTime,
-            FitLapFieldDefinition::TotalDistance,
-        ],
-    )
-    .unwrap();
-
-    // 1ST LAP (start at +1, takes 300s)
-    let mut data: [u8; 20] = [0; 20];
-    data[0..4].copy_from_slice(&(ts + 1).to_le_bytes()); // TimeStamp
-    data[4..8].copy_from_slice(&(ts).to_le_bytes()); // Start time
-    data[8..12].copy_from_slice(&(1u32 * 1000).to_le_bytes()); // Elapsed Time
-    data[12..16].copy_from_slice(&(1u32 * 1000).to_le_bytes()); // Timer Time
-    data[16..20].copy_from_slice(&(1u32 * 100).to_le_bytes()); // Distance
-    fit.push(&data).unwrap();
-
-    // Define an Session [required]
-    fit.define(
-        FitGlobalMessageType::Session,
-        &[
-            FitSessionFieldDefinition::Timestamp,
-            FitSessionFieldDefinition::MessageIndex,
-            FitSessionFieldDefinition::StartTime,
-            FitSessionFieldDefinition::TotalElapsedTime,
-            FitSessionFieldDefinition::TotalTimerTime,
-            FitSessionFieldDefinition::TotalDistance,
-        ],
-    )
-    .unwrap();
+    let ts = FitDateTime::new(938622559);
+    println!(
+        "Let's try to save a .fit file! Timestamp: {:?}",
+        ts.to_utc()
+    );
 
-    let mut data: [u8; 22] = [0; 22];
-    // Wrap up Session
-    data[0..4].copy_from_slice(&(ts + 1).to_le_bytes()); // TimeStamp
-    data[4..6].copy_from_slice(&(0u16).to_le_bytes()); // Index
-    data[6..10].copy_from_slice(&(ts).to_le_bytes()); // Start time
-    data[10..14].copy_from_slice(&(1u32 * 1000).to_le_bytes()); // Elapsed Time
-    data[14..18].copy_from_slice(&(1u32 * 1000).to_le_bytes()); // Timer Time
-    data[18..22].copy_from_slice(&(1u32 * 100).to_le_bytes()); // Distance
-    fit.push(&data).unwrap();
+    let sink = FileSink(File::create("activity.fit").unwrap());
+    let fit =
+        FitEncoder::with_sink(sink, FitFileType::Activity, ts, FitFileOptions::default()).unwrap();
 
-    // Define an Activity [required]
-    fit.define(
-        FitGlobalMessageType::Activity,
-        &[
-            FitActivityFieldDefinition::Timestamp,
-            FitActivityFieldDefinition::NumSessions,
-            FitActivityFieldDefinition::LocalTimestamp,
-            FitActivityFieldDefinition::TotalTimerTime,
-        ],
-    )
-    .unwrap();
+    // Writes the timer Events, Laps, Session and Activity around the samples
+    let mut recorder =
+        ActivityRecorder::start(fit, FitSportType::Running, FitSubSportType::Street, ts).unwrap();
+    // Recorded in CEST, UTC+2
+    recorder.set_utc_offset(2 * 3600);
 
-    // Wrap up Activity
-    let mut data: [u8; 14] = [0; 14];
-    data[0..4].copy_from_slice(&(ts + 1).to_le_bytes()); // TimeStamp
-    data[4..6].copy_from_slice(&(1u16).to_le_bytes()); // Num Sessions
-    data[6..10].copy_from_slice(&(ts + 1).to_le_bytes()); // Local timestamp
-    data[10..14].copy_from_slice(&(1u32 * 1000).to_le_bytes()); // Timer Time
-    fit.push(&data).unwrap();
+    for n in 1..=600 {
+        let timestamp = ts + n;
+        // Positions in microdegrees, as reported by the GPS
+        let position = FitPosition::from_microdegrees(52_370_216 + 30 * n as i32, 4_895_168);
+        recorder
+            .record(&ActivitySample {
+                timestamp,
+                position,
+                altitude: Some(2.5),
+                distance: Some(3.3 * n as f32),
+                speed: Some(3.3),
+                heart_rate: Some(130 + (n % 20) as u8),
+                ..Default::default()
+            })
+            .unwrap();
 
-    let buf = fit.done().unwrap();
-    // println!("{:02X?}", buf);
+        match n {
+            // Wait at a traffic light
+            200 => recorder.pause(timestamp).unwrap(),
+            230 => recorder.resume(timestamp).unwrap(),
+            // Lap every 5 minutes
+            300 => recorder.lap(timestamp).unwrap(),
+            _ => {}
+        }
+    }
 
-    let mut file = File::create("activity.fit").unwrap();
-    file.write_all(buf).unwrap();
+    let fit = recorder.finish(ts + 600).unwrap();
+    println!("Saved activity.fit, {} bytes", fit.size());
 }
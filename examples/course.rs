@@ -0,0 +1,54 @@
+use std::{fs::File, io::Write};
+
+use fit_no_std::{
+    CourseBuilder, CoursePoint, CourseTrackPoint, FitCoursePointType, FitDateTime, FitFile,
+    FitFileType, FitPosition, FitSportType,
+};
+
+fn main() {
+    // Unix time, as kept by the system clock
+    let ts = FitDateTime::from_unix(1758989430).unwrap();
+    println!(
+        "Let's try to save a Course .fit file! Timestamp: {:?}",
+        ts.to_utc()
+    );
+
+    // A climb out of the city and back down to the lake.
+    let track: Vec<CourseTrackPoint> = [
+        (46.2044, 6.1432, 375.0),
+        (46.2101, 6.1517, 390.0),
+        (46.2183, 6.1602, 431.0),
+        (46.2240, 6.1711, 470.0),
+        (46.2196, 6.1825, 412.0),
+        (46.2117, 6.1898, 376.0),
+    ]
+    .iter()
+    .map(|&(lat, long, altitude)| CourseTrackPoint {
+        position: FitPosition::from_degrees(lat, long).unwrap(),
+        altitude: Some(altitude),
+    })
+    .collect();
+    let points = [
+        CoursePoint::new(1, FitCoursePointType::Left).name("Route de Chene"),
+        CoursePoint::new(3, FitCoursePointType::Summit).name("Top"),
+        CoursePoint::new(5, FitCoursePointType::Water),
+    ];
+
+    // A Course file has:
+    // - File Id [1]
+    // - Course [1]
+    // - Lap [1]
+    // - Event timer start, Record [1 .. N], Event timer stop
+    // - Course Point [0 .. N]
+    let mut fit = FitFile::<1024>::new(FitFileType::Course, ts).unwrap();
+    let course = CourseBuilder::new("Lake loop", FitSportType::Cycling, &track)
+        .points(&points)
+        .speed(6.0);
+    course.write(&mut fit, ts).unwrap();
+    println!("Course of {:.0} m", course.distance());
+
+    let buf = fit.done().unwrap();
+
+    let mut file = File::create("course.fit").unwrap();
+    file.write_all(buf).unwrap();
+}
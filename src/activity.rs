@@ -0,0 +1,592 @@
+use crate::{
+    fit::{FitEncoder, FitError, FitLocalMessage},
+    position::FitPosition,
+    profile::{
+        stored_value, FitActivityFieldDefinition, FitEventFieldDefinition, FitLapFieldDefinition,
+        FitRecordFieldDefinition, FitSessionFieldDefinition,
+    },
+    sink::FitSink,
+    time::FitDateTime,
+    types::{
+        FitActivityType, FitEventType, FitEventTypeType, FitGlobalMessageType, FitLapTriggerType,
+        FitSessionTriggerType, FitSportType, FitSubSportType, FitTimerTriggerType,
+    },
+    value::FitValue,
+};
+
+/// Sensor readings at a point in time. Missing readings are `None`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ActivitySample {
+    pub timestamp: FitDateTime,
+    pub position: Option<FitPosition>,
+    /// Altitude in m.
+    pub altitude: Option<f32>,
+    /// Distance since the start of the activity in m.
+    pub distance: Option<f32>,
+    /// Speed in m/s.
+    pub speed: Option<f32>,
+    /// Heart rate in bpm.
+    pub heart_rate: Option<u8>,
+    /// Cadence in rpm.
+    pub cadence: Option<u8>,
+    /// Power in watts.
+    pub power: Option<u16>,
+    /// Temperature in degrees Celsius.
+    pub temperature: Option<i8>,
+}
+
+/// Records an Activity file from sensor samples.
+///
+/// The recorder writes the messages an Activity file requires: timer Events
+/// when it starts, pauses and resumes, a Record per sample, a Lap per
+/// [`ActivityRecorder::lap`], and the Session and Activity summaries when
+/// it finishes. Lap and session totals are computed from the samples, and
+/// exclude the time the timer is paused.
+#[derive(Debug)]
+pub struct ActivityRecorder<S> {
+    fit: FitEncoder<S>,
+    event: FitLocalMessage,
+    record: FitLocalMessage,
+    lap: FitLocalMessage,
+    sport: FitSportType,
+    sub_sport: FitSubSportType,
+    utc_offset: Option<i32>,
+    // Start of the running timer, `None` while paused.
+    running_since: Option<FitDateTime>,
+    distance: Option<f32>,
+    laps: u16,
+    lap_totals: Totals,
+    session_totals: Totals,
+}
+
+/// Totals of a lap or of the session.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Totals {
+    start_time: FitDateTime,
+    start_position: Option<FitPosition>,
+    end_position: Option<FitPosition>,
+    start_distance: Option<f32>,
+    // Seconds the timer ran.
+    timer_time: u32,
+    speed: Stat,
+    heart_rate: Stat,
+    cadence: Stat,
+    power: Stat,
+}
+
+/// Average and maximum of sensor readings.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Stat {
+    sum: f32,
+    count: u32,
+    max: f32,
+}
+
+const EVENT_FIELDS: [FitEventFieldDefinition; 4] = [
+    FitEventFieldDefinition::Timestamp,
+    FitEventFieldDefinition::Event,
+    FitEventFieldDefinition::EventType,
+    FitEventFieldDefinition::Data,
+];
+
+const RECORD_FIELDS: [FitRecordFieldDefinition; 10] = [
+    FitRecordFieldDefinition::Timestamp,
+    FitRecordFieldDefinition::PositionLat,
+    FitRecordFieldDefinition::PositionLong,
+    FitRecordFieldDefinition::Altitude,
+    FitRecordFieldDefinition::Distance,
+    FitRecordFieldDefinition::Speed,
+    FitRecordFieldDefinition::HeartRate,
+    FitRecordFieldDefinition::Cadence,
+    FitRecordFieldDefinition::Power,
+    FitRecordFieldDefinition::Temperature,
+];
+
+const LAP_FIELDS: [FitLapFieldDefinition; 23] = [
+    FitLapFieldDefinition::MessageIndex,
+    FitLapFieldDefinition::Timestamp,
+    FitLapFieldDefinition::Event,
+    FitLapFieldDefinition::EventType,
+    FitLapFieldDefinition::StartTime,
+    FitLapFieldDefinition::StartPositionLat,
+    FitLapFieldDefinition::StartPositionLong,
+    FitLapFieldDefinition::EndPositionLat,
+    FitLapFieldDefinition::EndPositionLong,
+    FitLapFieldDefinition::TotalElapsedTime,
+    FitLapFieldDefinition::TotalTimerTime,
+    FitLapFieldDefinition::TotalDistance,
+    FitLapFieldDefinition::AvgSpeed,
+    FitLapFieldDefinition::MaxSpeed,
+    FitLapFieldDefinition::AvgHeartRate,
+    FitLapFieldDefinition::MaxHeartRate,
+    FitLapFieldDefinition::AvgCadence,
+    FitLapFieldDefinition::MaxCadence,
+    FitLapFieldDefinition::AvgPower,
+    FitLapFieldDefinition::MaxPower,
+    FitLapFieldDefinition::LapTrigger,
+    FitLapFieldDefinition::Sport,
+    FitLapFieldDefinition::SubSport,
+];
+
+const SESSION_FIELDS: [FitSessionFieldDefinition; 23] = [
+    FitSessionFieldDefinition::MessageIndex,
+    FitSessionFieldDefinition::Timestamp,
+    FitSessionFieldDefinition::Event,
+    FitSessionFieldDefinition::EventType,
+    FitSessionFieldDefinition::StartTime,
+    FitSessionFieldDefinition::StartPositionLat,
+    FitSessionFieldDefinition::StartPositionLong,
+    FitSessionFieldDefinition::Sport,
+    FitSessionFieldDefinition::SubSport,
+    FitSessionFieldDefinition::TotalElapsedTime,
+    FitSessionFieldDefinition::TotalTimerTime,
+    FitSessionFieldDefinition::TotalDistance,
+    FitSessionFieldDefinition::AvgSpeed,
+    FitSessionFieldDefinition::MaxSpeed,
+    FitSessionFieldDefinition::AvgHeartRate,
+    FitSessionFieldDefinition::MaxHeartRate,
+    FitSessionFieldDefinition::AvgCadence,
+    FitSessionFieldDefinition::MaxCadence,
+    FitSessionFieldDefinition::AvgPower,
+    FitSessionFieldDefinition::MaxPower,
+    FitSessionFieldDefinition::FirstLapIndex,
+    FitSessionFieldDefinition::NumLaps,
+    FitSessionFieldDefinition::Trigger,
+];
+
+const ACTIVITY_FIELDS: [FitActivityFieldDefinition; 7] = [
+    FitActivityFieldDefinition::Timestamp,
+    FitActivityFieldDefinition::TotalTimerTime,
+    FitActivityFieldDefinition::NumSessions,
+    FitActivityFieldDefinition::Type,
+    FitActivityFieldDefinition::Event,
+    FitActivityFieldDefinition::EventType,
+    FitActivityFieldDefinition::LocalTimestamp,
+];
+
+impl<S: FitSink> ActivityRecorder<S> {
+    /// Start recording an activity of `sport` at `timestamp`, to a file
+    /// with just its File Id, and start the timer.
+    pub fn start(
+        mut fit: FitEncoder<S>,
+        sport: FitSportType,
+        sub_sport: FitSubSportType,
+        timestamp: FitDateTime,
+    ) -> Result<Self, FitError> {
+        let event = fit.define(FitGlobalMessageType::Event, &EVENT_FIELDS)?;
+        let record = fit.define(FitGlobalMessageType::Record, &RECORD_FIELDS)?;
+        let lap = fit.define(FitGlobalMessageType::Lap, &LAP_FIELDS)?;
+
+        let mut recorder = Self {
+            fit,
+            event,
+            record,
+            lap,
+            sport,
+            sub_sport,
+            utc_offset: None,
+            running_since: None,
+            distance: None,
+            laps: 0,
+            lap_totals: Totals::new(timestamp, None, None),
+            session_totals: Totals::new(timestamp, None, None),
+        };
+        recorder.resume(timestamp)?;
+        Ok(recorder)
+    }
+
+    /// Offset of the local time from UTC in seconds, to write the local
+    /// timestamp of the Activity.
+    pub fn set_utc_offset(&mut self, utc_offset: i32) {
+        self.utc_offset = Some(utc_offset);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.running_since.is_none()
+    }
+
+    /// Number of laps written so far.
+    pub fn laps(&self) -> u16 {
+        self.laps
+    }
+
+    /// Write a Record of the sample.
+    ///
+    /// Samples taken while paused are written, but aren't counted in the
+    /// averages and maximums.
+    pub fn record(&mut self, sample: &ActivitySample) -> Result<(), FitError> {
+        let position = sample.position;
+        self.fit.push_values(
+            self.record,
+            &[
+                (FitRecordFieldDefinition::Timestamp, sample.timestamp.into()),
+                (
+                    FitRecordFieldDefinition::PositionLat,
+                    position.map(|p| p.lat).into(),
+                ),
+                (
+                    FitRecordFieldDefinition::PositionLong,
+                    position.map(|p| p.long).into(),
+                ),
+                stored_value(FitRecordFieldDefinition::Altitude, sample.altitude),
+                stored_value(FitRecordFieldDefinition::Distance, sample.distance),
+                stored_value(FitRecordFieldDefinition::Speed, sample.speed),
+                (
+                    FitRecordFieldDefinition::HeartRate,
+                    sample.heart_rate.into(),
+                ),
+                (FitRecordFieldDefinition::Cadence, sample.cadence.into()),
+                (FitRecordFieldDefinition::Power, sample.power.into()),
+                (
+                    FitRecordFieldDefinition::Temperature,
+                    sample.temperature.into(),
+                ),
+            ],
+        )?;
+
+        if sample.distance.is_some() {
+            self.distance = sample.distance;
+        }
+        let running = !self.is_paused();
+        for totals in [&mut self.lap_totals, &mut self.session_totals] {
+            totals.add(sample, running);
+        }
+        Ok(())
+    }
+
+    /// Stop the timer, writing a timer stop Event. Does nothing if paused.
+    pub fn pause(&mut self, timestamp: FitDateTime) -> Result<(), FitError> {
+        if self.is_paused() {
+            return Ok(());
+        }
+        self.stop_timer(timestamp);
+        self.push_event(timestamp, FitEventType::Timer, FitEventTypeType::StopAll)
+    }
+
+    /// Restart the timer, writing a timer start Event. Does nothing if
+    /// running.
+    pub fn resume(&mut self, timestamp: FitDateTime) -> Result<(), FitError> {
+        if !self.is_paused() {
+            return Ok(());
+        }
+        self.push_event(timestamp, FitEventType::Timer, FitEventTypeType::Start)?;
+        self.running_since = Some(timestamp);
+        Ok(())
+    }
+
+    /// End the current lap at `timestamp`, writing its Lap, and start the
+    /// next one.
+    pub fn lap(&mut self, timestamp: FitDateTime) -> Result<(), FitError> {
+        self.push_lap(timestamp, FitLapTriggerType::Manual)
+    }
+
+    /// End the activity at `timestamp`, stopping the timer and writing the
+    /// last Lap, the Session and the Activity. Returns the finalized file.
+    pub fn finish(mut self, timestamp: FitDateTime) -> Result<FitEncoder<S>, FitError> {
+        self.pause(timestamp)?;
+        // An activity has at least a lap, only write the last one if it
+        // isn't empty.
+        if self.laps == 0 || self.lap_totals.start_time < timestamp {
+            self.push_lap(timestamp, FitLapTriggerType::SessionEnd)?;
+        }
+
+        let totals = self.session_totals;
+        let timer_time = totals.timer_time as f32;
+        let session = self
+            .fit
+            .define(FitGlobalMessageType::Session, &SESSION_FIELDS)?;
+        self.fit.push_values(
+            session,
+            &[
+                (FitSessionFieldDefinition::MessageIndex, 0u16.into()),
+                (FitSessionFieldDefinition::Timestamp, timestamp.into()),
+                (
+                    FitSessionFieldDefinition::Event,
+                    FitValue::Enum(FitEventType::Session as u8),
+                ),
+                (
+                    FitSessionFieldDefinition::EventType,
+                    FitValue::Enum(FitEventTypeType::Stop as u8),
+                ),
+                (
+                    FitSessionFieldDefinition::StartTime,
+                    totals.start_time.into(),
+                ),
+                (
+                    FitSessionFieldDefinition::StartPositionLat,
+                    totals.start_position.map(|p| p.lat).into(),
+                ),
+                (
+                    FitSessionFieldDefinition::StartPositionLong,
+                    totals.start_position.map(|p| p.long).into(),
+                ),
+                (
+                    FitSessionFieldDefinition::Sport,
+                    FitValue::Enum(self.sport as u8),
+                ),
+                (
+                    FitSessionFieldDefinition::SubSport,
+                    FitValue::Enum(self.sub_sport as u8),
+                ),
+                stored_value(
+                    FitSessionFieldDefinition::TotalElapsedTime,
+                    Some(elapsed(totals.start_time, timestamp)),
+                ),
+                stored_value(FitSessionFieldDefinition::TotalTimerTime, Some(timer_time)),
+                stored_value(
+                    FitSessionFieldDefinition::TotalDistance,
+                    totals.distance(self.distance),
+                ),
+                stored_value(
+                    FitSessionFieldDefinition::AvgSpeed,
+                    totals.avg_speed(self.distance),
+                ),
+                stored_value(FitSessionFieldDefinition::MaxSpeed, totals.speed.max()),
+                stored_value(
+                    FitSessionFieldDefinition::AvgHeartRate,
+                    totals.heart_rate.avg(),
+                ),
+                stored_value(
+                    FitSessionFieldDefinition::MaxHeartRate,
+                    totals.heart_rate.max(),
+                ),
+                stored_value(FitSessionFieldDefinition::AvgCadence, totals.cadence.avg()),
+                stored_value(FitSessionFieldDefinition::MaxCadence, totals.cadence.max()),
+                stored_value(FitSessionFieldDefinition::AvgPower, totals.power.avg()),
+                stored_value(FitSessionFieldDefinition::MaxPower, totals.power.max()),
+                (FitSessionFieldDefinition::FirstLapIndex, 0u16.into()),
+                (FitSessionFieldDefinition::NumLaps, self.laps.into()),
+                (
+                    FitSessionFieldDefinition::Trigger,
+                    FitValue::Enum(FitSessionTriggerType::ActivityEnd as u8),
+                ),
+            ],
+        )?;
+
+        let activity = self
+            .fit
+            .define(FitGlobalMessageType::Activity, &ACTIVITY_FIELDS)?;
+        let local_timestamp = self
+            .utc_offset
+            .and_then(|utc_offset| timestamp.to_local(utc_offset));
+        self.fit.push_values(
+            activity,
+            &[
+                (FitActivityFieldDefinition::Timestamp, timestamp.into()),
+                stored_value(FitActivityFieldDefinition::TotalTimerTime, Some(timer_time)),
+                (FitActivityFieldDefinition::NumSessions, 1u16.into()),
+                (
+                    FitActivityFieldDefinition::Type,
+                    FitValue::Enum(FitActivityType::Manual as u8),
+                ),
+                (
+                    FitActivityFieldDefinition::Event,
+                    FitValue::Enum(FitEventType::Activity as u8),
+                ),
+                (
+                    FitActivityFieldDefinition::EventType,
+                    FitValue::Enum(FitEventTypeType::Stop as u8),
+                ),
+                (
+                    FitActivityFieldDefinition::LocalTimestamp,
+                    local_timestamp.into(),
+                ),
+            ],
+        )?;
+
+        self.fit.finish()?;
+        Ok(self.fit)
+    }
+
+    /// Adds the time the timer ran until `timestamp` to the totals.
+    fn stop_timer(&mut self, timestamp: FitDateTime) {
+        if let Some(since) = self.running_since.take() {
+            let timer_time = timestamp.seconds().saturating_sub(since.seconds());
+            self.lap_totals.timer_time += timer_time;
+            self.session_totals.timer_time += timer_time;
+        }
+    }
+
+    fn push_event(
+        &mut self,
+        timestamp: FitDateTime,
+        event: FitEventType,
+        event_type: FitEventTypeType,
+    ) -> Result<(), FitError> {
+        self.fit.push_values(
+            self.event,
+            &[
+                (FitEventFieldDefinition::Timestamp, timestamp.into()),
+                (FitEventFieldDefinition::Event, FitValue::Enum(event as u8)),
+                (
+                    FitEventFieldDefinition::EventType,
+                    FitValue::Enum(event_type as u8),
+                ),
+                (
+                    FitEventFieldDefinition::Data,
+                    (FitTimerTriggerType::Manual as u32).into(),
+                ),
+            ],
+        )
+    }
+
+    fn push_lap(
+        &mut self,
+        timestamp: FitDateTime,
+        trigger: FitLapTriggerType,
+    ) -> Result<(), FitError> {
+        // Split the running timer between this lap and the next.
+        let running = !self.is_paused();
+        self.stop_timer(timestamp);
+        if running {
+            self.running_since = Some(timestamp);
+        }
+
+        let totals = self.lap_totals;
+        self.fit.push_values(
+            self.lap,
+            &[
+                (FitLapFieldDefinition::MessageIndex, self.laps.into()),
+                (FitLapFieldDefinition::Timestamp, timestamp.into()),
+                (
+                    FitLapFieldDefinition::Event,
+                    FitValue::Enum(FitEventType::Lap as u8),
+                ),
+                (
+                    FitLapFieldDefinition::EventType,
+                    FitValue::Enum(FitEventTypeType::Stop as u8),
+                ),
+                (FitLapFieldDefinition::StartTime, totals.start_time.into()),
+                (
+                    FitLapFieldDefinition::StartPositionLat,
+                    totals.start_position.map(|p| p.lat).into(),
+                ),
+                (
+                    FitLapFieldDefinition::StartPositionLong,
+                    totals.start_position.map(|p| p.long).into(),
+                ),
+                (
+                    FitLapFieldDefinition::EndPositionLat,
+                    totals.end_position.map(|p| p.lat).into(),
+                ),
+                (
+                    FitLapFieldDefinition::EndPositionLong,
+                    totals.end_position.map(|p| p.long).into(),
+                ),
+                stored_value(
+                    FitLapFieldDefinition::TotalElapsedTime,
+                    Some(elapsed(totals.start_time, timestamp)),
+                ),
+                stored_value(
+                    FitLapFieldDefinition::TotalTimerTime,
+                    Some(totals.timer_time as f32),
+                ),
+                stored_value(
+                    FitLapFieldDefinition::TotalDistance,
+                    totals.distance(self.distance),
+                ),
+                stored_value(
+                    FitLapFieldDefinition::AvgSpeed,
+                    totals.avg_speed(self.distance),
+                ),
+                stored_value(FitLapFieldDefinition::MaxSpeed, totals.speed.max()),
+                stored_value(FitLapFieldDefinition::AvgHeartRate, totals.heart_rate.avg()),
+                stored_value(FitLapFieldDefinition::MaxHeartRate, totals.heart_rate.max()),
+                stored_value(FitLapFieldDefinition::AvgCadence, totals.cadence.avg()),
+                stored_value(FitLapFieldDefinition::MaxCadence, totals.cadence.max()),
+                stored_value(FitLapFieldDefinition::AvgPower, totals.power.avg()),
+                stored_value(FitLapFieldDefinition::MaxPower, totals.power.max()),
+                (
+                    FitLapFieldDefinition::LapTrigger,
+                    FitValue::Enum(trigger as u8),
+                ),
+                (
+                    FitLapFieldDefinition::Sport,
+                    FitValue::Enum(self.sport as u8),
+                ),
+                (
+                    FitLapFieldDefinition::SubSport,
+                    FitValue::Enum(self.sub_sport as u8),
+                ),
+            ],
+        )?;
+
+        self.laps += 1;
+        self.lap_totals = Totals::new(timestamp, totals.end_position, self.distance);
+        Ok(())
+    }
+}
+
+impl Totals {
+    fn new(
+        start_time: FitDateTime,
+        start_position: Option<FitPosition>,
+        start_distance: Option<f32>,
+    ) -> Self {
+        Self {
+            start_time,
+            start_position,
+            end_position: start_position,
+            start_distance,
+            timer_time: 0,
+            speed: Stat::default(),
+            heart_rate: Stat::default(),
+            cadence: Stat::default(),
+            power: Stat::default(),
+        }
+    }
+
+    fn add(&mut self, sample: &ActivitySample, running: bool) {
+        if sample.position.is_some() {
+            self.start_position = self.start_position.or(sample.position);
+            self.end_position = sample.position;
+        }
+        if self.start_distance.is_none() {
+            // Distance before the first sample with one.
+            self.start_distance = sample.distance.map(|_| 0.0);
+        }
+        if running {
+            self.speed.add(sample.speed);
+            self.heart_rate.add(sample.heart_rate.map(f32::from));
+            self.cadence.add(sample.cadence.map(f32::from));
+            self.power.add(sample.power.map(f32::from));
+        }
+    }
+
+    /// Distance covered, given the distance of the last sample.
+    fn distance(&self, distance: Option<f32>) -> Option<f32> {
+        Some(distance? - self.start_distance?)
+    }
+
+    /// Distance over the timer time, or the average speed of the samples
+    /// without distance.
+    fn avg_speed(&self, distance: Option<f32>) -> Option<f32> {
+        match self.distance(distance) {
+            Some(distance) if self.timer_time > 0 => Some(distance / self.timer_time as f32),
+            _ => self.speed.avg(),
+        }
+    }
+}
+
+impl Stat {
+    fn add(&mut self, value: Option<f32>) {
+        if let Some(value) = value {
+            self.sum += value;
+            self.count += 1;
+            self.max = self.max.max(value);
+        }
+    }
+
+    fn avg(&self) -> Option<f32> {
+        (self.count > 0).then(|| self.sum / self.count as f32)
+    }
+
+    fn max(&self) -> Option<f32> {
+        (self.count > 0).then_some(self.max)
+    }
+}
+
+/// Seconds between two timestamps.
+fn elapsed(start: FitDateTime, end: FitDateTime) -> f32 {
+    end.seconds().saturating_sub(start.seconds()) as f32
+}
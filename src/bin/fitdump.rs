@@ -0,0 +1,265 @@
+//! Prints the records of a FIT file, to inspect files a device rejects.
+//!
+//! Usage: `fitdump <file.fit>`, or `fitdump -` to read standard input.
+//!
+//! Exits with 1 if the file is malformed, including a CRC mismatch, and
+//! with 2 if it can't be read. Exits quietly when the output is closed,
+//! e.g. piped to `head`.
+
+use std::{
+    env, fs,
+    io::{self, ErrorKind, Read, Write},
+    process::ExitCode,
+};
+
+use fit_no_std::{
+    FitDataField, FitDataMessage, FitDateTime, FitDecodeError, FitDecoder, FitDefinitionMessage,
+    FitHeader, FitRecord, FitValue,
+};
+
+const TIMESTAMP_FIELD_NUMBER: u8 = 253;
+
+fn main() -> ExitCode {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: fitdump <file.fit | ->");
+        return ExitCode::from(2);
+    };
+    let data = if path == "-" {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data).map(|_| data)
+    } else {
+        fs::read(&path)
+    };
+    let data = match data {
+        Ok(data) => data,
+        Err(error) => {
+            eprintln!("fitdump: {path}: {error}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut out = io::stdout().lock();
+    match dump(&mut out, &path, &data) {
+        Ok(code) => code,
+        Err(error) if error.kind() == ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("fitdump: {error}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn dump(out: &mut impl Write, path: &str, data: &[u8]) -> io::Result<ExitCode> {
+    writeln!(out, "{path}: {} bytes", data.len())?;
+    let decoder = match FitDecoder::new(data) {
+        Ok(decoder) => decoder,
+        Err(error) => {
+            writeln!(out, "malformed header: {error:?}")?;
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    let header = *decoder.header();
+    print_header(out, &header)?;
+
+    let offset = header.size as usize + header.data_size as usize;
+    let crc = u16::from_le_bytes([data[offset], data[offset + 1]]);
+    let mut end = header.size as usize;
+    for record in decoder {
+        match record {
+            Ok(FitRecord::Definition(definition)) => {
+                print_definition(out, &definition)?;
+                end = definition.offset() + definition_size(&definition);
+            }
+            Ok(FitRecord::Data(message)) => {
+                print_data(out, &message)?;
+                end = message.offset() + 1 + message.data().len();
+            }
+            Err(FitDecodeError::CrcMismatch) => {
+                writeln!(out, "{offset:>8}  file CRC 0x{crc:04X} invalid")?;
+                return Ok(ExitCode::FAILURE);
+            }
+            Err(error) => {
+                writeln!(out, "{end:>8}  malformed record: {error:?}")?;
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+
+    writeln!(out, "{offset:>8}  file CRC 0x{crc:04X} valid")?;
+    if data.len() > offset + 2 {
+        writeln!(
+            out,
+            "{:>8}  {} bytes after the file CRC, e.g. a chained file",
+            offset + 2,
+            data.len() - offset - 2
+        )?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_header(out: &mut impl Write, header: &FitHeader) -> io::Result<()> {
+    let crc = match header.crc {
+        Some(0) => "not computed".to_string(),
+        Some(crc) => format!("0x{crc:04X} valid"),
+        None => "none".to_string(),
+    };
+    writeln!(
+        out,
+        "{:>8}  header: {} bytes, protocol {}.{}, profile {}.{}, {} data bytes, CRC {crc}",
+        0,
+        header.size,
+        header.protocol_version >> 4,
+        header.protocol_version & 0x0F,
+        header.profile_version / 1000,
+        header.profile_version % 1000,
+        header.data_size,
+    )
+}
+
+fn print_definition(out: &mut impl Write, definition: &FitDefinitionMessage) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>8}  definition local {}: {} ({}), {:?}",
+        definition.offset(),
+        definition.local_type(),
+        message_name(definition.global_message().map(|m| m.name())),
+        definition.global_msg_num(),
+        definition.arch(),
+    )?;
+    for field in definition.fields() {
+        let base_type = field
+            .base_type()
+            .map_or("unknown".to_string(), |base_type| format!("{base_type:?}"));
+        writeln!(
+            out,
+            "            {:>3} {:<28} {base_type:<8} {:>3} bytes",
+            field.number(),
+            field.name().unwrap_or("unknown"),
+            field.size(),
+        )?;
+    }
+    for [number, size, index] in definition.developer_fields() {
+        writeln!(
+            out,
+            "            {number:>3} developer {index:<18} {size:>12} bytes"
+        )?;
+    }
+    Ok(())
+}
+
+fn print_data(out: &mut impl Write, message: &FitDataMessage) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>8}  data local {}: {} ({})",
+        message.offset(),
+        message.local_type(),
+        message_name(message.global_message().map(|m| m.name())),
+        message.global_msg_num(),
+    )?;
+    // Fields start after the record header.
+    let mut offset = message.offset() + 1;
+    for field in message.fields() {
+        writeln!(
+            out,
+            "{offset:>8}    {:>3} {:<28} {}",
+            field.number(),
+            field.name().unwrap_or("unknown"),
+            field_value(&field),
+        )?;
+        offset += field.data().len();
+    }
+    for field in message.developer_fields() {
+        writeln!(
+            out,
+            "{offset:>8}    {:>3} developer {:<18} {:02X?}",
+            field.number(),
+            field.developer_data_index(),
+            field.data(),
+        )?;
+        offset += field.data().len();
+    }
+    if let Some(timestamp) = message.timestamp() {
+        if message.field(TIMESTAMP_FIELD_NUMBER).is_none() {
+            writeln!(
+                out,
+                "              compressed timestamp {}",
+                date_time(timestamp)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn message_name(name: Option<&str>) -> &str {
+    name.unwrap_or("unknown")
+}
+
+/// Decoded value, then its physical value and units if it has any.
+fn field_value(field: &FitDataField) -> String {
+    let Some(value) = field.value() else {
+        return format!("invalid {:02X?}", field.data());
+    };
+    let mut text = match value {
+        FitValue::String(text) => format!("{text:?}"),
+        FitValue::Byte(bytes) => format!("{bytes:02X?}"),
+        FitValue::Array(array) => {
+            let values: Vec<_> = array.iter().map(|value| number(&value)).collect();
+            format!("[{}]", values.join(", "))
+        }
+        value => number(&value),
+    };
+    if field.number() == TIMESTAMP_FIELD_NUMBER {
+        if let FitValue::Uint32(seconds) = value {
+            text = format!("{text} ({})", date_time(FitDateTime::new(seconds)));
+        }
+    } else if field.units() == Some("semicircles") {
+        if let Some(degrees) = field.semicircles().and_then(|s| s.to_degrees()) {
+            text = format!("{text} ({degrees:.7} degrees)");
+        }
+    } else if let (Some(physical), Some(units)) = (field.physical_value(), field.units()) {
+        if !units.is_empty() {
+            let decimals = decimals(field.scale().unwrap_or(1.0));
+            text = format!("{text} ({physical:.decimals$} {units})");
+        }
+    }
+    text
+}
+
+/// Decimals resolving a scale, `ceil(log10(scale))`.
+fn decimals(scale: f64) -> usize {
+    let mut decimals = 0;
+    while 10f64.powi(decimals) < scale {
+        decimals += 1;
+    }
+    decimals as usize
+}
+
+fn number(value: &FitValue) -> String {
+    match *value {
+        FitValue::Enum(v) | FitValue::Uint8(v) | FitValue::Uint8z(v) => v.to_string(),
+        FitValue::Sint8(v) => v.to_string(),
+        FitValue::Sint16(v) => v.to_string(),
+        FitValue::Uint16(v) | FitValue::Uint16z(v) => v.to_string(),
+        FitValue::Sint32(v) => v.to_string(),
+        FitValue::Uint32(v) | FitValue::Uint32z(v) => v.to_string(),
+        FitValue::Sint64(v) => v.to_string(),
+        FitValue::Uint64(v) | FitValue::Uint64z(v) => v.to_string(),
+        FitValue::Float32(v) => v.to_string(),
+        FitValue::Float64(v) => v.to_string(),
+        ref value => format!("{value:?}"),
+    }
+}
+
+fn date_time(time: FitDateTime) -> String {
+    match time.to_utc() {
+        Some(utc) => format!("{utc}Z"),
+        None => format!("system time {}", time.seconds()),
+    }
+}
+
+/// Size of a definition record, with its header.
+fn definition_size(definition: &FitDefinitionMessage) -> usize {
+    let developer = definition.developer_fields().count();
+    let developer = if developer > 0 { 1 + developer * 3 } else { 0 };
+    1 + 5 + definition.fields().count() * 3 + developer
+}
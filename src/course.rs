@@ -0,0 +1,400 @@
+use crate::{
+    fit::{truncate, FitEncoder, FitError, FitLocalMessage},
+    position::FitPosition,
+    profile::{
+        stored_value, FitCourseFieldDefinition, FitCoursePointFieldDefinition,
+        FitEventFieldDefinition, FitFieldDefinition, FitLapFieldDefinition,
+        FitRecordFieldDefinition,
+    },
+    sink::FitSink,
+    time::FitDateTime,
+    types::{
+        FitCoursePointType, FitEventType, FitEventTypeType, FitGlobalMessageType,
+        FitLapTriggerType, FitSportType, FitTimerTriggerType,
+    },
+    value::FitValue,
+};
+
+/// `course_capabilities` of a course with positions, distances and times.
+const CAPABILITIES: u32 = 0x02 | 0x04 | 0x08 | 0x10;
+
+#[derive(Debug, PartialEq)]
+pub enum CourseError {
+    /// Course point is at an index past the end of the track.
+    PointOutOfTrack,
+    /// Course points aren't in the order of the track.
+    PointsOutOfOrder,
+    Fit(FitError),
+}
+
+/// Point of the polyline of a course.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CourseTrackPoint {
+    pub position: FitPosition,
+    /// Altitude in m.
+    pub altitude: Option<f32>,
+}
+
+/// Cue along a course, such as a turn, a water point or a summit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoursePoint<'a> {
+    /// Index of the track point the cue is at.
+    pub index: usize,
+    pub kind: FitCoursePointType,
+    pub name: Option<&'a str>,
+}
+
+/// Builds the messages of a Course file from a track and its cues.
+///
+/// Devices expect a Course, then a Lap with the totals, the track as
+/// Records between timer start and stop Events, and the Course Points.
+/// Distances are cumulative along the track, and times are those of a
+/// virtual partner at a constant speed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CourseBuilder<'a> {
+    name: &'a str,
+    sport: FitSportType,
+    track: &'a [CourseTrackPoint],
+    points: &'a [CoursePoint<'a>],
+    speed: f32,
+}
+
+impl<'a> CoursePoint<'a> {
+    pub fn new(index: usize, kind: FitCoursePointType) -> Self {
+        Self {
+            index,
+            kind,
+            name: None,
+        }
+    }
+
+    pub fn name(mut self, name: &'a str) -> Self {
+        self.name = Some(name);
+        self
+    }
+}
+
+impl<'a> CourseBuilder<'a> {
+    /// Course of `sport` following `track`, at 5 m/s.
+    pub fn new(name: &'a str, sport: FitSportType, track: &'a [CourseTrackPoint]) -> Self {
+        Self {
+            name,
+            sport,
+            track,
+            points: &[],
+            speed: 5.0,
+        }
+    }
+
+    /// Cues along the track, in the order they're reached.
+    pub fn points(mut self, points: &'a [CoursePoint<'a>]) -> Self {
+        self.points = points;
+        self
+    }
+
+    /// Speed of the virtual partner in m/s.
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Length of the track in m.
+    pub fn distance(&self) -> f32 {
+        self.distances().last().unwrap_or(0.0)
+    }
+
+    /// Write the course, starting at `start`.
+    ///
+    /// Fails with [`CourseError::PointOutOfTrack`] if a course point is past
+    /// the end of the track, and with [`CourseError::PointsOutOfOrder`] if
+    /// the course points aren't in the order of the track.
+    pub fn write<S: FitSink>(
+        &self,
+        fit: &mut FitEncoder<S>,
+        start: FitDateTime,
+    ) -> Result<(), CourseError> {
+        let mut previous = 0;
+        for point in self.points {
+            if point.index >= self.track.len() {
+                return Err(CourseError::PointOutOfTrack);
+            }
+            if point.index < previous {
+                return Err(CourseError::PointsOutOfOrder);
+            }
+            previous = point.index;
+        }
+
+        let name = truncate(self.name, u8::MAX as usize - 1);
+        let course = fit.define(
+            FitGlobalMessageType::Course,
+            &[
+                FitCourseFieldDefinition::Sport.into(),
+                FitCourseFieldDefinition::Capabilities.into(),
+                FitCourseFieldDefinition::Name.string(name.len() as u8 + 1)?,
+            ],
+        )?;
+        fit.push_values(
+            course,
+            &[
+                (
+                    FitCourseFieldDefinition::Sport,
+                    FitValue::Enum(self.sport as u8),
+                ),
+                (
+                    FitCourseFieldDefinition::Capabilities,
+                    FitValue::Uint32z(CAPABILITIES),
+                ),
+                (FitCourseFieldDefinition::Name, name.into()),
+            ],
+        )?;
+
+        let distance = self.distance();
+        let end = self.time_at(start, distance);
+        let (ascent, descent) = self.climb();
+        let first = self.track.first().map(|point| point.position);
+        let last = self.track.last().map(|point| point.position);
+        let lap = fit.define(FitGlobalMessageType::Lap, &LAP_FIELDS)?;
+        fit.push_values(
+            lap,
+            &[
+                (FitLapFieldDefinition::Timestamp, end.into()),
+                (FitLapFieldDefinition::StartTime, start.into()),
+                (
+                    FitLapFieldDefinition::StartPositionLat,
+                    first.map(|p| p.lat).into(),
+                ),
+                (
+                    FitLapFieldDefinition::StartPositionLong,
+                    first.map(|p| p.long).into(),
+                ),
+                (
+                    FitLapFieldDefinition::EndPositionLat,
+                    last.map(|p| p.lat).into(),
+                ),
+                (
+                    FitLapFieldDefinition::EndPositionLong,
+                    last.map(|p| p.long).into(),
+                ),
+                stored_value(
+                    FitLapFieldDefinition::TotalElapsedTime,
+                    Some(self.elapsed(distance)),
+                ),
+                stored_value(
+                    FitLapFieldDefinition::TotalTimerTime,
+                    Some(self.elapsed(distance)),
+                ),
+                stored_value(FitLapFieldDefinition::TotalDistance, Some(distance)),
+                stored_value(FitLapFieldDefinition::TotalAscent, ascent),
+                stored_value(FitLapFieldDefinition::TotalDescent, descent),
+                (
+                    FitLapFieldDefinition::LapTrigger,
+                    FitValue::Enum(FitLapTriggerType::SessionEnd as u8),
+                ),
+                (
+                    FitLapFieldDefinition::Sport,
+                    FitValue::Enum(self.sport as u8),
+                ),
+            ],
+        )?;
+
+        let event = fit.define(FitGlobalMessageType::Event, &EVENT_FIELDS)?;
+        push_event(fit, event, start, FitEventTypeType::Start)?;
+
+        let record = fit.define(FitGlobalMessageType::Record, &RECORD_FIELDS)?;
+        for (point, distance) in self.track.iter().zip(self.distances()) {
+            fit.push_values(
+                record,
+                &[
+                    (
+                        FitRecordFieldDefinition::Timestamp,
+                        self.time_at(start, distance).into(),
+                    ),
+                    (
+                        FitRecordFieldDefinition::PositionLat,
+                        point.position.lat.into(),
+                    ),
+                    (
+                        FitRecordFieldDefinition::PositionLong,
+                        point.position.long.into(),
+                    ),
+                    stored_value(FitRecordFieldDefinition::Altitude, point.altitude),
+                    stored_value(FitRecordFieldDefinition::Distance, Some(distance)),
+                ],
+            )?;
+        }
+
+        push_event(fit, event, end, FitEventTypeType::StopDisableAll)?;
+
+        if self.points.is_empty() {
+            return Ok(());
+        }
+        // Names of all the course points are as long as the longest.
+        let len = self
+            .points
+            .iter()
+            .filter_map(|point| point.name)
+            .map(|name| truncate(name, u8::MAX as usize - 1).len() as u8 + 1)
+            .max()
+            .unwrap_or(1);
+        let course_point = fit.define(
+            FitGlobalMessageType::CoursePoint,
+            &[
+                FitCoursePointFieldDefinition::MessageIndex.into(),
+                FitCoursePointFieldDefinition::Timestamp.into(),
+                FitCoursePointFieldDefinition::PositionLat.into(),
+                FitCoursePointFieldDefinition::PositionLong.into(),
+                FitCoursePointFieldDefinition::Distance.into(),
+                FitCoursePointFieldDefinition::Type.into(),
+                FitCoursePointFieldDefinition::Name.string(len)?,
+            ],
+        )?;
+        // Course points are in track order, so the distances are walked once.
+        let mut distances = self.distances();
+        let mut distance = distances.next().unwrap_or(0.0);
+        let mut at = 0;
+        for (index, point) in self.points.iter().enumerate() {
+            while at < point.index {
+                distance = distances.next().unwrap_or(distance);
+                at += 1;
+            }
+            let position = self.track[point.index].position;
+            let name = point.name.map(|name| truncate(name, u8::MAX as usize - 1));
+            fit.push_values(
+                course_point,
+                &[
+                    (
+                        FitCoursePointFieldDefinition::MessageIndex,
+                        (index as u16).into(),
+                    ),
+                    (
+                        FitCoursePointFieldDefinition::Timestamp,
+                        self.time_at(start, distance).into(),
+                    ),
+                    (
+                        FitCoursePointFieldDefinition::PositionLat,
+                        position.lat.into(),
+                    ),
+                    (
+                        FitCoursePointFieldDefinition::PositionLong,
+                        position.long.into(),
+                    ),
+                    stored_value(FitCoursePointFieldDefinition::Distance, Some(distance)),
+                    (
+                        FitCoursePointFieldDefinition::Type,
+                        FitValue::Enum(point.kind as u8),
+                    ),
+                    (FitCoursePointFieldDefinition::Name, name.into()),
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Cumulative distance in m at each point of the track. Segments with
+    /// an invalid position add no distance.
+    fn distances(&self) -> impl Iterator<Item = f32> + 'a {
+        let mut previous: Option<FitPosition> = None;
+        let mut total = 0.0;
+        self.track.iter().map(move |point| {
+            if let Some(previous) = previous {
+                total += previous.distance(&point.position).unwrap_or(0.0);
+            }
+            previous = Some(point.position);
+            total as f32
+        })
+    }
+
+    /// Total ascent and descent in m, `None` without altitudes.
+    fn climb(&self) -> (Option<f32>, Option<f32>) {
+        let mut altitudes = self.track.iter().filter_map(|point| point.altitude);
+        let Some(mut previous) = altitudes.next() else {
+            return (None, None);
+        };
+        let (mut ascent, mut descent) = (0.0, 0.0);
+        for altitude in altitudes {
+            if altitude > previous {
+                ascent += altitude - previous;
+            } else {
+                descent += previous - altitude;
+            }
+            previous = altitude;
+        }
+        (Some(ascent), Some(descent))
+    }
+
+    /// Seconds for the virtual partner to cover `distance`.
+    fn elapsed(&self, distance: f32) -> f32 {
+        if self.speed > 0.0 {
+            distance / self.speed
+        } else {
+            0.0
+        }
+    }
+
+    fn time_at(&self, start: FitDateTime, distance: f32) -> FitDateTime {
+        start + (self.elapsed(distance) + 0.5) as u32
+    }
+}
+
+impl From<FitError> for CourseError {
+    fn from(error: FitError) -> Self {
+        Self::Fit(error)
+    }
+}
+
+const LAP_FIELDS: [FitLapFieldDefinition; 13] = [
+    FitLapFieldDefinition::Timestamp,
+    FitLapFieldDefinition::StartTime,
+    FitLapFieldDefinition::StartPositionLat,
+    FitLapFieldDefinition::StartPositionLong,
+    FitLapFieldDefinition::EndPositionLat,
+    FitLapFieldDefinition::EndPositionLong,
+    FitLapFieldDefinition::TotalElapsedTime,
+    FitLapFieldDefinition::TotalTimerTime,
+    FitLapFieldDefinition::TotalDistance,
+    FitLapFieldDefinition::TotalAscent,
+    FitLapFieldDefinition::TotalDescent,
+    FitLapFieldDefinition::LapTrigger,
+    FitLapFieldDefinition::Sport,
+];
+
+const EVENT_FIELDS: [FitEventFieldDefinition; 4] = [
+    FitEventFieldDefinition::Timestamp,
+    FitEventFieldDefinition::Event,
+    FitEventFieldDefinition::EventType,
+    FitEventFieldDefinition::Data,
+];
+
+const RECORD_FIELDS: [FitRecordFieldDefinition; 5] = [
+    FitRecordFieldDefinition::Timestamp,
+    FitRecordFieldDefinition::PositionLat,
+    FitRecordFieldDefinition::PositionLong,
+    FitRecordFieldDefinition::Altitude,
+    FitRecordFieldDefinition::Distance,
+];
+
+fn push_event<S: FitSink>(
+    fit: &mut FitEncoder<S>,
+    event: FitLocalMessage,
+    timestamp: FitDateTime,
+    event_type: FitEventTypeType,
+) -> Result<(), FitError> {
+    fit.push_values(
+        event,
+        &[
+            (FitEventFieldDefinition::Timestamp, timestamp.into()),
+            (
+                FitEventFieldDefinition::Event,
+                FitValue::Enum(FitEventType::Timer as u8),
+            ),
+            (
+                FitEventFieldDefinition::EventType,
+                FitValue::Enum(event_type as u8),
+            ),
+            (
+                FitEventFieldDefinition::Data,
+                (FitTimerTriggerType::Manual as u32).into(),
+            ),
+        ],
+    )
+}
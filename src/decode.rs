@@ -0,0 +1,570 @@
+use crate::{
+    fit::{crc_get16, FitMessageArchitecture},
+    position::{FitPosition, FitSemicircles},
+    profile::{field_name, field_physical, field_scale, field_units, FitFieldDefinition},
+    time::FitDateTime,
+    types::{FitBaseType, FitGlobalMessageType},
+    value::FitValue,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum FitDecodeError {
+    /// Header is too short or has no ".FIT" data type.
+    InvalidHeader,
+    /// Header CRC doesn't match the header content.
+    HeaderCrcMismatch,
+    /// Data ends in the middle of a record, or before the file CRC.
+    UnexpectedEnd,
+    /// Data Message of a local message type that was never defined.
+    UndefinedLocalType(u8),
+    /// Compressed timestamp header before any full timestamp.
+    MissingTimestamp,
+    /// File CRC doesn't match the file content.
+    CrcMismatch,
+}
+
+const HEADER_SIZE: usize = 12;
+const HEADER_WITH_CRC_SIZE: usize = 14;
+
+const DEFINITION_MESSAGE: u8 = 0x40;
+const DEVELOPER_DATA: u8 = 0x20;
+const COMPRESSED_TIMESTAMP_HEADER: u8 = 0x80;
+const LOCAL_MESSAGE_TYPE_MASK: u8 = 0x0F;
+const TIMESTAMP_FIELD_NUMBER: u8 = 253;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FitHeader {
+    /// Header size, 12 or 14 bytes.
+    pub size: u8,
+    pub protocol_version: u8,
+    pub profile_version: u16,
+    /// Size of the records, without the header and the file CRC.
+    pub data_size: u32,
+    /// Header CRC, only in 14 byte headers. Zero if it wasn't computed.
+    pub crc: Option<u16>,
+}
+
+/// A Definition Message, borrowing its field definitions from the file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FitDefinitionMessage<'a> {
+    offset: usize,
+    local_type: u8,
+    arch: FitMessageArchitecture,
+    global_msg_num: u16,
+    fields: &'a [u8],
+    developer_fields: &'a [u8],
+}
+
+impl<'a> FitDefinitionMessage<'a> {
+    /// Offset of the record header within the file.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn local_type(&self) -> u8 {
+        self.local_type
+    }
+
+    pub fn arch(&self) -> FitMessageArchitecture {
+        self.arch
+    }
+
+    pub fn global_msg_num(&self) -> u16 {
+        self.global_msg_num
+    }
+
+    /// Global message in the profile, if known.
+    pub fn global_message(&self) -> Option<FitGlobalMessageType> {
+        FitGlobalMessageType::try_from(self.global_msg_num).ok()
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = FitDefinitionField> + 'a {
+        let global_msg_num = self.global_msg_num;
+        self.fields
+            .chunks_exact(3)
+            .map(move |f| FitDefinitionField {
+                global_msg_num,
+                number: f[0],
+                size: f[1],
+                base_type: f[2],
+            })
+    }
+
+    /// Developer fields, as (field number, size, developer data index).
+    pub fn developer_fields(&self) -> impl Iterator<Item = [u8; 3]> + 'a {
+        self.developer_fields
+            .chunks_exact(3)
+            .map(|f| [f[0], f[1], f[2]])
+    }
+
+    /// Size of the Data Messages of this definition, without the record header.
+    pub fn data_size(&self) -> usize {
+        let fields: usize = self.fields.chunks_exact(3).map(|f| f[1] as usize).sum();
+        let developer_fields: usize = self
+            .developer_fields
+            .chunks_exact(3)
+            .map(|f| f[1] as usize)
+            .sum();
+        fields + developer_fields
+    }
+}
+
+/// A field of a Definition Message.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FitDefinitionField {
+    global_msg_num: u16,
+    number: u8,
+    size: u8,
+    base_type: u8,
+}
+
+impl FitDefinitionField {
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    pub fn size(&self) -> u8 {
+        self.size
+    }
+
+    /// Base type in the profile, if known.
+    pub fn base_type(&self) -> Option<FitBaseType> {
+        FitBaseType::try_from(self.base_type).ok()
+    }
+
+    /// Field name in the profile, if known.
+    pub fn name(&self) -> Option<&'static str> {
+        let global_msg_num = FitGlobalMessageType::try_from(self.global_msg_num).ok()?;
+        field_name(&global_msg_num, self.number)
+    }
+
+    /// Field units in the profile, if known. Empty for fields without units.
+    pub fn units(&self) -> Option<&'static str> {
+        let global_msg_num = FitGlobalMessageType::try_from(self.global_msg_num).ok()?;
+        field_units(&global_msg_num, self.number)
+    }
+}
+
+/// A Data Message, borrowing its content from the file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FitDataMessage<'a> {
+    offset: usize,
+    definition: FitDefinitionMessage<'a>,
+    timestamp: Option<u32>,
+    data: &'a [u8],
+}
+
+impl<'a> FitDataMessage<'a> {
+    /// Offset of the record header within the file.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn definition(&self) -> &FitDefinitionMessage<'a> {
+        &self.definition
+    }
+
+    pub fn local_type(&self) -> u8 {
+        self.definition.local_type
+    }
+
+    pub fn global_msg_num(&self) -> u16 {
+        self.definition.global_msg_num
+    }
+
+    /// Global message in the profile, if known.
+    pub fn global_message(&self) -> Option<FitGlobalMessageType> {
+        self.definition.global_message()
+    }
+
+    /// Timestamp of the message, either from its Timestamp field or from a
+    /// compressed timestamp header.
+    pub fn timestamp(&self) -> Option<FitDateTime> {
+        self.timestamp.map(FitDateTime::new)
+    }
+
+    /// Content of the message, without the record header.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn fields(&self) -> FitDataFields<'a> {
+        FitDataFields {
+            definition: self.definition,
+            fields: self.definition.fields,
+            data: self.data,
+        }
+    }
+
+    /// First field with the field number.
+    pub fn field(&self, number: u8) -> Option<FitDataField<'a>> {
+        self.fields().find(|f| f.number() == number)
+    }
+
+    /// Position of the latitude and longitude fields, e.g. `PositionLat` and
+    /// `PositionLong` of a Record. `None` if either is missing or invalid.
+    pub fn position<T: FitFieldDefinition>(&self, lat: T, long: T) -> Option<FitPosition> {
+        let semicircles = |field: T| self.field(field.field_number())?.semicircles();
+        Some(FitPosition {
+            lat: semicircles(lat)?,
+            long: semicircles(long)?,
+        })
+    }
+
+    /// Developer fields, after the native fields.
+    pub fn developer_fields(&self) -> impl Iterator<Item = FitDeveloperDataField<'a>> + 'a {
+        let native_size: usize = self
+            .definition
+            .fields
+            .chunks_exact(3)
+            .map(|f| f[1] as usize)
+            .sum();
+        let mut data = &self.data[native_size..];
+        self.definition.developer_fields().map(move |f| {
+            let (field, rest) = data.split_at(f[1] as usize);
+            data = rest;
+            FitDeveloperDataField {
+                number: f[0],
+                developer_data_index: f[2],
+                data: field,
+            }
+        })
+    }
+}
+
+/// A developer field of a Data Message.
+///
+/// Its base type, name and units come from the matching Field Description
+/// message.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FitDeveloperDataField<'a> {
+    number: u8,
+    developer_data_index: u8,
+    data: &'a [u8],
+}
+
+impl<'a> FitDeveloperDataField<'a> {
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    pub fn developer_data_index(&self) -> u8 {
+        self.developer_data_index
+    }
+
+    /// Raw bytes of the field.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// Iterator over the fields of a Data Message.
+#[derive(Debug, Clone)]
+pub struct FitDataFields<'a> {
+    definition: FitDefinitionMessage<'a>,
+    fields: &'a [u8],
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for FitDataFields<'a> {
+    type Item = FitDataField<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (field, fields) = self.fields.split_first_chunk::<3>()?;
+        let (data, rest) = self.data.split_at(field[1] as usize);
+        self.fields = fields;
+        self.data = rest;
+
+        Some(FitDataField {
+            definition: FitDefinitionField {
+                global_msg_num: self.definition.global_msg_num,
+                number: field[0],
+                size: field[1],
+                base_type: field[2],
+            },
+            arch: self.definition.arch,
+            data,
+        })
+    }
+}
+
+/// A field of a Data Message.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FitDataField<'a> {
+    definition: FitDefinitionField,
+    arch: FitMessageArchitecture,
+    data: &'a [u8],
+}
+
+impl<'a> FitDataField<'a> {
+    pub fn number(&self) -> u8 {
+        self.definition.number
+    }
+
+    /// Base type in the profile, if known.
+    pub fn base_type(&self) -> Option<FitBaseType> {
+        self.definition.base_type()
+    }
+
+    /// Field name in the profile, if known.
+    pub fn name(&self) -> Option<&'static str> {
+        self.definition.name()
+    }
+
+    /// Raw bytes of the field.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Value of the field, if its base type is known and its size matches.
+    ///
+    /// Returns `None` for missing values, written as the invalid value of
+    /// the base type, see [`FitBaseType::invalid`].
+    pub fn value(&self) -> Option<FitValue<'a>> {
+        let base_type = self.base_type()?;
+        if base_type.is_invalid(self.arch, self.data) {
+            return None;
+        }
+        FitValue::decode(base_type, self.arch, self.data)
+    }
+
+    /// Field units in the profile, if known.
+    pub fn units(&self) -> Option<&'static str> {
+        self.definition.units()
+    }
+
+    /// Field scale in the profile, if known.
+    pub fn scale(&self) -> Option<f64> {
+        let global_msg_num = FitGlobalMessageType::try_from(self.definition.global_msg_num).ok()?;
+        field_scale(&global_msg_num, self.number())
+    }
+
+    /// Physical value of the field, with the scale and offset of its field
+    /// in the profile. `None` if the field isn't known or numeric.
+    pub fn physical_value(&self) -> Option<f64> {
+        let global_msg_num = FitGlobalMessageType::try_from(self.definition.global_msg_num).ok()?;
+        field_physical(&global_msg_num, self.number(), &self.value()?)
+    }
+
+    /// Value of a `sint32` field in semicircles, such as a position.
+    pub fn semicircles(&self) -> Option<FitSemicircles> {
+        match self.value()? {
+            FitValue::Sint32(semicircles) => Some(FitSemicircles::new(semicircles)),
+            _ => None,
+        }
+    }
+
+    /// Physical value of the field, in the units of the field of `T` with
+    /// its number, see [`FitFieldDefinition::physical`].
+    ///
+    /// Subfields share the number of their main field, use
+    /// [`FitFieldDefinition::physical`] with the subfield for their scale.
+    pub fn physical<T: FitFieldDefinition>(&self) -> Option<f64> {
+        T::from_field_number(self.number())?.physical(&self.value()?)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FitRecord<'a> {
+    Definition(FitDefinitionMessage<'a>),
+    Data(FitDataMessage<'a>),
+}
+
+/// Streaming FIT decoder over the bytes of a file.
+///
+/// Records are yielded in order, borrowing from the file. The file CRC is
+/// checked once the last record is read, and any error ends the iteration.
+#[derive(Debug)]
+pub struct FitDecoder<'a> {
+    data: &'a [u8],
+    header: FitHeader,
+    offset: usize,
+    end: usize,
+    crc: u16,
+    definitions: [Option<FitDefinitionMessage<'a>>; 16],
+    last_timestamp: Option<u32>,
+    done: bool,
+}
+
+impl<'a> FitDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, FitDecodeError> {
+        let size = *data.first().ok_or(FitDecodeError::InvalidHeader)? as usize;
+        if size < HEADER_SIZE || data.len() < size {
+            return Err(FitDecodeError::InvalidHeader);
+        }
+        if &data[8..12] != b".FIT" {
+            return Err(FitDecodeError::InvalidHeader);
+        }
+
+        let crc = if size >= HEADER_WITH_CRC_SIZE {
+            let crc = u16::from_le_bytes([data[12], data[13]]);
+            if crc != 0 && crc != data[..12].iter().fold(0, |crc, b| crc_get16(crc, *b)) {
+                return Err(FitDecodeError::HeaderCrcMismatch);
+            }
+            Some(crc)
+        } else {
+            None
+        };
+
+        let header = FitHeader {
+            size: size as u8,
+            protocol_version: data[1],
+            profile_version: u16::from_le_bytes([data[2], data[3]]),
+            data_size: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+            crc,
+        };
+
+        // [Header][Records][CRC], with a data size that may not fit in memory.
+        let end = usize::try_from(header.data_size)
+            .ok()
+            .and_then(|data_size| size.checked_add(data_size))
+            .filter(|end| end.checked_add(2).is_some_and(|len| len <= data.len()))
+            .ok_or(FitDecodeError::UnexpectedEnd)?;
+
+        Ok(Self {
+            data,
+            header,
+            offset: size,
+            end,
+            crc: data[..size].iter().fold(0, |crc, b| crc_get16(crc, *b)),
+            definitions: [None; 16],
+            last_timestamp: None,
+            done: false,
+        })
+    }
+
+    pub fn header(&self) -> &FitHeader {
+        &self.header
+    }
+
+    /// Takes the next `len` bytes of the records, updating the file CRC.
+    fn take(&mut self, len: usize) -> Result<&'a [u8], FitDecodeError> {
+        if self.end - self.offset < len {
+            return Err(FitDecodeError::UnexpectedEnd);
+        }
+        let bytes = &self.data[self.offset..self.offset + len];
+        self.offset += len;
+        self.crc = bytes.iter().fold(self.crc, |crc, b| crc_get16(crc, *b));
+        Ok(bytes)
+    }
+
+    fn decode_definition(
+        &mut self,
+        offset: usize,
+        header: u8,
+    ) -> Result<FitDefinitionMessage<'a>, FitDecodeError> {
+        // [0] Reserved [1] Architecture [2:4] Global Message Number [4] Fields
+        let content = self.take(5)?;
+        let arch = match content[1] {
+            0 => FitMessageArchitecture::LSB,
+            _ => FitMessageArchitecture::MSB,
+        };
+        let global_msg_num = match arch {
+            FitMessageArchitecture::LSB => u16::from_le_bytes([content[2], content[3]]),
+            FitMessageArchitecture::MSB => u16::from_be_bytes([content[2], content[3]]),
+        };
+        let fields = self.take(content[4] as usize * 3)?;
+
+        let developer_fields = if header & DEVELOPER_DATA != 0 {
+            let num_fields = self.take(1)?[0];
+            self.take(num_fields as usize * 3)?
+        } else {
+            &[]
+        };
+
+        let definition = FitDefinitionMessage {
+            offset,
+            local_type: header & LOCAL_MESSAGE_TYPE_MASK,
+            arch,
+            global_msg_num,
+            fields,
+            developer_fields,
+        };
+        self.definitions[definition.local_type as usize] = Some(definition);
+        Ok(definition)
+    }
+
+    fn decode_data(
+        &mut self,
+        offset: usize,
+        header: u8,
+    ) -> Result<FitDataMessage<'a>, FitDecodeError> {
+        let compressed = header & COMPRESSED_TIMESTAMP_HEADER != 0;
+        let local_type = if compressed {
+            (header >> 5) & 0x03
+        } else {
+            header & LOCAL_MESSAGE_TYPE_MASK
+        };
+        let definition = self.definitions[local_type as usize]
+            .ok_or(FitDecodeError::UndefinedLocalType(local_type))?;
+
+        let timestamp = if compressed {
+            // The time offset is relative to the last timestamp, rolling
+            // over every 32 seconds.
+            let last = self
+                .last_timestamp
+                .ok_or(FitDecodeError::MissingTimestamp)?;
+            let time_offset = (header & 0x1F) as u32;
+            let ts = (last & !0x1F) + time_offset;
+            Some(if time_offset < last & 0x1F {
+                ts.wrapping_add(0x20)
+            } else {
+                ts
+            })
+        } else {
+            None
+        };
+
+        let data = self.take(definition.data_size())?;
+        let mut msg = FitDataMessage {
+            offset,
+            definition,
+            timestamp,
+            data,
+        };
+        if let Some(FitValue::Uint32(ts)) =
+            msg.field(TIMESTAMP_FIELD_NUMBER).and_then(|f| f.value())
+        {
+            msg.timestamp = Some(ts);
+        }
+        if msg.timestamp.is_some() {
+            self.last_timestamp = msg.timestamp;
+        }
+        Ok(msg)
+    }
+
+    fn decode_record(&mut self) -> Result<FitRecord<'a>, FitDecodeError> {
+        let offset = self.offset;
+        let header = self.take(1)?[0];
+        if header & COMPRESSED_TIMESTAMP_HEADER == 0 && header & DEFINITION_MESSAGE != 0 {
+            Ok(FitRecord::Definition(
+                self.decode_definition(offset, header)?,
+            ))
+        } else {
+            Ok(FitRecord::Data(self.decode_data(offset, header)?))
+        }
+    }
+}
+
+impl<'a> Iterator for FitDecoder<'a> {
+    type Item = Result<FitRecord<'a>, FitDecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        if self.offset == self.end {
+            self.done = true;
+            let crc = u16::from_le_bytes([self.data[self.end], self.data[self.end + 1]]);
+            if crc != self.crc {
+                return Some(Err(FitDecodeError::CrcMismatch));
+            }
+            return None;
+        }
+
+        let record = self.decode_record();
+        self.done = record.is_err();
+        Some(record)
+    }
+}
@@ -0,0 +1,332 @@
+use std::io::{self, Write};
+
+use crate::{
+    activity::ActivitySample,
+    decode::{FitDataMessage, FitDecodeError, FitDecoder, FitRecord},
+    profile::{
+        FitFieldDefinition, FitLapFieldDefinition, FitRecordFieldDefinition,
+        FitSessionFieldDefinition,
+    },
+    time::FitDateTime,
+    types::{FitGlobalMessageType, FitSportType},
+    value::FitValue,
+};
+
+const GPX_HEADER: &str = r#"<gpx version="1.1" creator="fit-no-std" xmlns="http://www.topografix.com/GPX/1/1" xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v2">"#;
+const TCX_HEADER: &str = r#"<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2" xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2">"#;
+/// UTC time of FIT timestamp 0, for files without any time.
+const FIT_EPOCH: &str = "1989-12-31T00:00:00Z";
+
+/// Track of an Activity, from its decoded Record, Lap and Session
+/// messages, to export as GPX or TCX.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExportTrack {
+    pub sport: Option<FitSportType>,
+    /// Records with a timestamp, in the order of the file.
+    pub points: Vec<ActivitySample>,
+    pub laps: Vec<ExportLap>,
+}
+
+/// Totals of a Lap message.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ExportLap {
+    pub start_time: Option<FitDateTime>,
+    /// End of the lap.
+    pub timestamp: Option<FitDateTime>,
+    /// Time in s.
+    pub total_elapsed_time: Option<f32>,
+    /// Distance in m.
+    pub total_distance: Option<f32>,
+    /// Speed in m/s.
+    pub max_speed: Option<f32>,
+    pub total_calories: Option<u16>,
+    pub avg_heart_rate: Option<u8>,
+    pub max_heart_rate: Option<u8>,
+    pub avg_cadence: Option<u8>,
+}
+
+impl ExportTrack {
+    /// Track of the Record, Lap and Session messages of a FIT file.
+    pub fn from_fit(data: &[u8]) -> Result<Self, FitDecodeError> {
+        let mut track = Self::default();
+        for record in FitDecoder::new(data)? {
+            if let FitRecord::Data(message) = record? {
+                track.push(&message);
+            }
+        }
+        Ok(track)
+    }
+
+    /// Adds a Record, Lap or Session message. Other messages, and Records
+    /// without a timestamp, are ignored.
+    pub fn push(&mut self, message: &FitDataMessage) {
+        match message.global_message() {
+            Some(FitGlobalMessageType::Record) => {
+                let Some(timestamp) = message.timestamp() else {
+                    return;
+                };
+                let value = |field: FitRecordFieldDefinition| {
+                    message
+                        .field(field.field_number())?
+                        .physical::<FitRecordFieldDefinition>()
+                };
+                use FitRecordFieldDefinition as Record;
+                self.points.push(ActivitySample {
+                    timestamp,
+                    position: message.position(Record::PositionLat, Record::PositionLong),
+                    altitude: value(Record::Altitude).map(|v| v as f32),
+                    distance: value(Record::Distance).map(|v| v as f32),
+                    speed: value(Record::Speed).map(|v| v as f32),
+                    heart_rate: value(Record::HeartRate).map(|v| v as u8),
+                    cadence: value(Record::Cadence).map(|v| v as u8),
+                    power: value(Record::Power).map(|v| v as u16),
+                    temperature: value(Record::Temperature).map(|v| v as i8),
+                });
+            }
+            Some(FitGlobalMessageType::Lap) => {
+                let value = |field: FitLapFieldDefinition| {
+                    message
+                        .field(field.field_number())?
+                        .physical::<FitLapFieldDefinition>()
+                };
+                let time = |field: FitLapFieldDefinition| match value(field)? {
+                    seconds if seconds >= 0.0 => Some(FitDateTime::new(seconds as u32)),
+                    _ => None,
+                };
+                use FitLapFieldDefinition as Lap;
+                self.laps.push(ExportLap {
+                    start_time: time(Lap::StartTime),
+                    timestamp: time(Lap::Timestamp),
+                    total_elapsed_time: value(Lap::TotalElapsedTime).map(|v| v as f32),
+                    total_distance: value(Lap::TotalDistance).map(|v| v as f32),
+                    max_speed: value(Lap::MaxSpeed).map(|v| v as f32),
+                    total_calories: value(Lap::TotalCalories).map(|v| v as u16),
+                    avg_heart_rate: value(Lap::AvgHeartRate).map(|v| v as u8),
+                    max_heart_rate: value(Lap::MaxHeartRate).map(|v| v as u8),
+                    avg_cadence: value(Lap::AvgCadence).map(|v| v as u8),
+                });
+            }
+            Some(FitGlobalMessageType::Session) if self.sport.is_none() => {
+                let sport = message
+                    .field(FitSessionFieldDefinition::Sport.field_number())
+                    .and_then(|field| field.value());
+                if let Some(FitValue::Enum(sport)) = sport {
+                    self.sport = FitSportType::try_from(sport).ok();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes a GPX 1.1 track, with the heart rate, cadence, temperature
+    /// and speed in a Garmin TrackPointExtension. Points without a position,
+    /// or with a system time rather than a UTC time, are left out.
+    pub fn write_gpx<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, "{GPX_HEADER}")?;
+        if let Some(time) = self.points.iter().find_map(|p| iso8601(p.timestamp)) {
+            writeln!(out, "  <metadata><time>{time}</time></metadata>")?;
+        }
+        writeln!(out, "  <trk>")?;
+        if let Some(sport) = self.sport {
+            writeln!(out, "    <type>{}</type>", sport.name())?;
+        }
+        writeln!(out, "    <trkseg>")?;
+        for point in &self.points {
+            let Some((lat, lon)) = point.position.and_then(|p| p.to_degrees()) else {
+                continue;
+            };
+            let Some(time) = iso8601(point.timestamp) else {
+                continue;
+            };
+            writeln!(out, r#"      <trkpt lat="{lat:.7}" lon="{lon:.7}">"#)?;
+            if let Some(altitude) = point.altitude {
+                writeln!(out, "        <ele>{altitude:.1}</ele>")?;
+            }
+            writeln!(out, "        <time>{time}</time>")?;
+            if point.heart_rate.is_some()
+                || point.cadence.is_some()
+                || point.temperature.is_some()
+                || point.speed.is_some()
+            {
+                writeln!(out, "        <extensions><gpxtpx:TrackPointExtension>")?;
+                if let Some(temperature) = point.temperature {
+                    writeln!(out, "          <gpxtpx:atemp>{temperature}</gpxtpx:atemp>")?;
+                }
+                if let Some(heart_rate) = point.heart_rate {
+                    writeln!(out, "          <gpxtpx:hr>{heart_rate}</gpxtpx:hr>")?;
+                }
+                if let Some(cadence) = point.cadence {
+                    writeln!(out, "          <gpxtpx:cad>{cadence}</gpxtpx:cad>")?;
+                }
+                if let Some(speed) = point.speed {
+                    writeln!(out, "          <gpxtpx:speed>{speed:.3}</gpxtpx:speed>")?;
+                }
+                writeln!(out, "        </gpxtpx:TrackPointExtension></extensions>")?;
+            }
+            writeln!(out, "      </trkpt>")?;
+        }
+        writeln!(out, "    </trkseg>")?;
+        writeln!(out, "  </trk>")?;
+        writeln!(out, "</gpx>")
+    }
+
+    /// Writes a TCX activity, with a Lap per Lap message holding the points
+    /// up to its end, and heart rates, cadences and power. Without Lap
+    /// messages, all the points are in one lap. Points with a system time
+    /// rather than a UTC time are left out, as trackpoints need a time.
+    pub fn write_tcx<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let sport = match self.sport {
+            Some(FitSportType::Running) => "Running",
+            Some(FitSportType::Cycling) => "Biking",
+            _ => "Other",
+        };
+        // Id and lap start times are required, so they fall back to the
+        // first point with a UTC time, then to the FIT epoch.
+        let first = self
+            .points
+            .iter()
+            .map(|p| p.timestamp)
+            .find(|time| time.to_utc().is_some());
+        let laps = if self.laps.is_empty() {
+            vec![ExportLap {
+                start_time: first,
+                timestamp: self.points.last().map(|p| p.timestamp),
+                ..Default::default()
+            }]
+        } else {
+            self.laps.clone()
+        };
+
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, "{TCX_HEADER}")?;
+        writeln!(out, "  <Activities>")?;
+        writeln!(out, r#"    <Activity Sport="{sport}">"#)?;
+        let id = laps.first().and_then(|lap| lap.start_time).or(first);
+        let id = id.and_then(iso8601).unwrap_or_else(|| FIT_EPOCH.into());
+        writeln!(out, "      <Id>{id}</Id>")?;
+
+        let mut next = 0;
+        for (index, lap) in laps.iter().enumerate() {
+            // The last lap takes the points after the end of all laps.
+            let last = index == laps.len() - 1;
+            let count = self.points[next..]
+                .iter()
+                .take_while(|p| last || lap.timestamp.is_none_or(|end| p.timestamp <= end))
+                .count();
+            let points = &self.points[next..next + count];
+            next += count;
+
+            let start = lap.start_time.or(points.first().map(|p| p.timestamp));
+            let start = start.and_then(iso8601).unwrap_or_else(|| id.clone());
+            writeln!(out, r#"      <Lap StartTime="{start}">"#)?;
+            let time =
+                lap.total_elapsed_time
+                    .unwrap_or_else(|| match (lap.start_time, lap.timestamp) {
+                        (Some(start), Some(end)) => {
+                            end.seconds().saturating_sub(start.seconds()) as f32
+                        }
+                        _ => 0.0,
+                    });
+            writeln!(
+                out,
+                "        <TotalTimeSeconds>{time:.1}</TotalTimeSeconds>"
+            )?;
+            let distance = lap.total_distance.unwrap_or_else(|| {
+                let first = points.iter().find_map(|p| p.distance);
+                let last = points.iter().rev().find_map(|p| p.distance);
+                match (first, last) {
+                    (Some(first), Some(last)) => last - first,
+                    _ => 0.0,
+                }
+            });
+            writeln!(
+                out,
+                "        <DistanceMeters>{distance:.2}</DistanceMeters>"
+            )?;
+            if let Some(speed) = lap.max_speed {
+                writeln!(out, "        <MaximumSpeed>{speed:.3}</MaximumSpeed>")?;
+            }
+            let calories = lap.total_calories.unwrap_or(0);
+            writeln!(out, "        <Calories>{calories}</Calories>")?;
+            if let Some(heart_rate) = lap.avg_heart_rate {
+                writeln!(
+                    out,
+                    "        <AverageHeartRateBpm><Value>{heart_rate}</Value></AverageHeartRateBpm>"
+                )?;
+            }
+            if let Some(heart_rate) = lap.max_heart_rate {
+                writeln!(
+                    out,
+                    "        <MaximumHeartRateBpm><Value>{heart_rate}</Value></MaximumHeartRateBpm>"
+                )?;
+            }
+            writeln!(out, "        <Intensity>Active</Intensity>")?;
+            if let Some(cadence) = lap.avg_cadence {
+                writeln!(out, "        <Cadence>{cadence}</Cadence>")?;
+            }
+            writeln!(out, "        <TriggerMethod>Manual</TriggerMethod>")?;
+
+            writeln!(out, "        <Track>")?;
+            for point in points {
+                if let Some(time) = iso8601(point.timestamp) {
+                    write_trackpoint(&mut out, point, &time)?;
+                }
+            }
+            writeln!(out, "        </Track>")?;
+            writeln!(out, "      </Lap>")?;
+        }
+
+        writeln!(out, "    </Activity>")?;
+        writeln!(out, "  </Activities>")?;
+        writeln!(out, "</TrainingCenterDatabase>")
+    }
+}
+
+fn write_trackpoint<W: Write>(out: &mut W, point: &ActivitySample, time: &str) -> io::Result<()> {
+    writeln!(out, "          <Trackpoint>")?;
+    writeln!(out, "            <Time>{time}</Time>")?;
+    if let Some((lat, lon)) = point.position.and_then(|p| p.to_degrees()) {
+        writeln!(
+            out,
+            "            <Position><LatitudeDegrees>{lat:.7}</LatitudeDegrees><LongitudeDegrees>{lon:.7}</LongitudeDegrees></Position>"
+        )?;
+    }
+    if let Some(altitude) = point.altitude {
+        writeln!(
+            out,
+            "            <AltitudeMeters>{altitude:.1}</AltitudeMeters>"
+        )?;
+    }
+    if let Some(distance) = point.distance {
+        writeln!(
+            out,
+            "            <DistanceMeters>{distance:.2}</DistanceMeters>"
+        )?;
+    }
+    if let Some(heart_rate) = point.heart_rate {
+        writeln!(
+            out,
+            "            <HeartRateBpm><Value>{heart_rate}</Value></HeartRateBpm>"
+        )?;
+    }
+    if let Some(cadence) = point.cadence {
+        writeln!(out, "            <Cadence>{cadence}</Cadence>")?;
+    }
+    if point.speed.is_some() || point.power.is_some() {
+        writeln!(out, "            <Extensions><ns3:TPX>")?;
+        if let Some(speed) = point.speed {
+            writeln!(out, "              <ns3:Speed>{speed:.3}</ns3:Speed>")?;
+        }
+        if let Some(power) = point.power {
+            writeln!(out, "              <ns3:Watts>{power}</ns3:Watts>")?;
+        }
+        writeln!(out, "            </ns3:TPX></Extensions>")?;
+    }
+    writeln!(out, "          </Trackpoint>")
+}
+
+/// UTC time in ISO 8601, `None` for system times.
+fn iso8601(time: FitDateTime) -> Option<String> {
+    time.to_utc().map(|utc| format!("{utc}Z"))
+}
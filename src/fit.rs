@@ -1,13 +1,48 @@
 use heapless::Vec;
 
 use crate::{
-    profile::{FitFieldDefinition, FitFileIdFieldDefinition},
-    types::{FitFileManufacturerType, FitFileType, FitGlobalMessageType},
+    profile::{
+        FitDeveloperDataIdFieldDefinition, FitFieldDefinition, FitFieldDescriptionFieldDefinition,
+        FitFileIdFieldDefinition, FitSizedField,
+    },
+    sink::FitSink,
+    time::FitDateTime,
+    types::{FitBaseType, FitFileManufacturerType, FitFileType, FitGlobalMessageType},
+    value::{arch_bytes, le_bytes, FitValue},
 };
 
 #[derive(Debug, PartialEq)]
 pub enum FitError {
-    Failed(u8),
+    /// Buffer is missing `needed` bytes for the write.
+    BufferFull { needed: usize },
+    /// Data Message pushed without a definition in this file.
+    NoActiveDefinition,
+    /// Data doesn't have the size of its definition.
+    DataSizeMismatch { expected: usize, actual: usize },
+    /// Value at `index` doesn't match the field defined at that position.
+    FieldMismatch { index: usize },
+    /// Definition has more fields than a definition can hold.
+    TooManyFields,
+    /// Array field is larger than the 255 bytes a field can hold.
+    FieldTooLarge,
+    /// String field has no room for its null terminator.
+    FieldTooSmall,
+    /// File has more distinct definitions than it can hold.
+    TooManyDefinitions,
+    /// Compressed timestamps need a Timestamp field in the definition.
+    NoTimestampField,
+    /// Developer wasn't added to this file.
+    UnknownDeveloper,
+    /// File already has the 255 developers a developer data index can
+    /// address.
+    TooManyDevelopers,
+    /// File was finalized by [`FitEncoder::finish`].
+    AlreadyFinalized,
+    /// Data Messages of the definition, with their record header, are
+    /// larger than the records the encoder can assemble.
+    RecordTooLarge { size: usize },
+    /// Sink failed to write or patch the output.
+    Sink,
 }
 
 #[derive(Debug)]
@@ -22,186 +57,986 @@ pub enum FitMessageArchitecture {
     MSB,
 }
 
+impl FitMessageArchitecture {
+    /// Architecture of the target, to push values in their native byte order.
+    pub const fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            Self::MSB
+        } else {
+            Self::LSB
+        }
+    }
+}
+
+/// Size of the file header.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FitHeaderSize {
+    /// Legacy header, without the header CRC.
+    Legacy = 12,
+    Standard = 14,
+}
+
+/// Options to create a [`FitEncoder`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FitFileOptions<'a> {
+    pub header_size: FitHeaderSize,
+    /// Byte order of every message in the file.
+    pub arch: FitMessageArchitecture,
+    /// Device written in the File Id message.
+    pub file_id: FitFileId<'a>,
+}
+
+impl Default for FitFileOptions<'_> {
+    fn default() -> Self {
+        Self {
+            header_size: FitHeaderSize::Standard,
+            arch: FitMessageArchitecture::LSB,
+            file_id: FitFileId::default(),
+        }
+    }
+}
+
+/// Device that created the file, written in the File Id message along with
+/// the file type and creation time. Fields left to `None` are not written.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FitFileId<'a> {
+    pub manufacturer: FitFileManufacturerType,
+    pub product: Option<u16>,
+    pub serial_number: Option<u32>,
+    /// Number of the file, for files that are not unique by time created.
+    pub number: Option<u16>,
+    /// Truncated to 254 bytes.
+    pub product_name: Option<&'a str>,
+}
+
+impl Default for FitFileId<'_> {
+    fn default() -> Self {
+        Self {
+            manufacturer: FitFileManufacturerType::Development,
+            product: None,
+            serial_number: None,
+            number: None,
+            product_name: None,
+        }
+    }
+}
+
 pub enum FitProtocolVersion {
     Version1 = 1,
     Version2 = 2,
 }
 
+/// Handle to a message definition, returned by [`FitEncoder::define`].
+///
+/// The handle stays valid for the whole file, even if the local message type
+/// it was given is later reused by another definition. In that case the
+/// definition is emitted again before the next data message.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FitLocalMessage {
+    definition: u8,
+}
+
+/// Handle to a developer, returned by [`FitEncoder::add_developer`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FitDeveloper {
+    developer_data_index: u8,
+}
+
+/// Developer data field, returned by [`FitEncoder::describe_field`].
+///
+/// Developer fields are appended to the native fields of a definition with
+/// [`FitEncoder::define_with_developer_fields`], and their values are pushed
+/// after the native values.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FitDeveloperField {
+    developer_data_index: u8,
+    field_number: u8,
+    base_type: FitBaseType,
+    size: u8,
+}
+
+impl FitDeveloperField {
+    pub fn base_type(&self) -> FitBaseType {
+        self.base_type
+    }
+
+    /// Field holding an array of `count` values of its base type.
+    ///
+    /// Fails with [`FitError::FieldTooLarge`] past 255 bytes.
+    pub fn array(self, count: u8) -> Result<Self, FitError> {
+        let size = u8::try_from(self.base_type.size() * count as usize)
+            .map_err(|_e| FitError::FieldTooLarge)?;
+        Ok(Self { size, ..self })
+    }
+
+    /// String field of `len` bytes, including its null terminator.
+    ///
+    /// Fails with [`FitError::FieldTooSmall`] for 0 bytes.
+    pub fn string(self, len: u8) -> Result<Self, FitError> {
+        if len == 0 {
+            return Err(FitError::FieldTooSmall);
+        }
+        Ok(Self { size: len, ..self })
+    }
+
+    pub fn get(&self) -> [u8; 3] {
+        [self.field_number, self.size, self.developer_data_index]
+    }
+}
+
 #[derive(Debug, PartialEq)]
-pub struct FitFile<const N: usize> {
-    stream: Vec<u8, N>,
+struct FitDefinition {
+    global_msg_num: u16,
+    fields: Vec<[u8; 3], MAX_FIELDS>,
+    developer_fields: Vec<[u8; 3], MAX_DEVELOPER_FIELDS>,
+    // Local message type (0:15) currently holding this definition, if any.
+    local_type: Option<u8>,
+    // Timestamp carried by the record header, so the definition has no
+    // Timestamp field and must use local message types 0:3.
+    compressed_timestamp: bool,
+    // Definition without the Timestamp field, used for compressed timestamps.
+    compressed: Option<u8>,
+}
+
+/// Record assembled before it is written.
+struct Record {
+    bytes: [u8; MAX_RECORD_SIZE],
+    len: usize,
+}
+
+/// Data Message record, with the local message type of its header.
+struct DataRecord {
+    record: Record,
+    local_type: u8,
+    // Whether the record header holds the timestamp.
+    compressed: bool,
+}
+
+impl Record {
+    fn new() -> Self {
+        Self {
+            bytes: [0; MAX_RECORD_SIZE],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.reserve(bytes.len()).copy_from_slice(bytes);
+    }
+
+    /// Appends `len` zeros, returning them to fill in.
+    fn reserve(&mut self, len: usize) -> &mut [u8] {
+        let start = self.len;
+        self.len += len;
+        &mut self.bytes[start..self.len]
+    }
+}
+
+impl FitDefinition {
+    /// Size of a Data Message, without the record header.
+    fn data_size(&self) -> usize {
+        let fields: usize = self.fields.iter().map(|f| f[1] as usize).sum();
+        let developer_fields: usize = self.developer_fields.iter().map(|f| f[1] as usize).sum();
+        fields + developer_fields
+    }
+
+    /// Offset of the Timestamp field (253) within a Data Message.
+    fn timestamp_offset(&self) -> Option<usize> {
+        let mut offset = 0;
+        for field in &self.fields {
+            if field[0] == TIMESTAMP_FIELD_NUMBER && field[1] == 4 {
+                return Some(offset);
+            }
+            offset += field[1] as usize;
+        }
+        None
+    }
+}
+
+/// FIT file encoder, writing records to a [`FitSink`] as they are pushed.
+#[derive(Debug, PartialEq)]
+pub struct FitEncoder<S> {
+    sink: S,
+    // Bytes of records written after the header, and their CRC.
+    data_size: usize,
+    crc: u16,
+    header_size: FitHeaderSize,
     arch: FitMessageArchitecture,
-    manufacturer: FitFileManufacturerType,
+    definitions: Vec<FitDefinition, MAX_DEFINITIONS>,
+    // Definition index held by each local message type.
+    local_types: [Option<u8>; LOCAL_MESSAGE_TYPES],
+    // Last use of each local message type, to evict the least recently used.
+    local_types_usage: [u32; LOCAL_MESSAGE_TYPES],
+    usage: u32,
+    // Last full timestamp written, compressed timestamps are offsets from it.
+    last_timestamp: Option<u32>,
+    developers: u8,
+    finalized: bool,
 }
 
+/// FIT file encoder, holding the whole file in memory.
+pub type FitFile<const N: usize> = FitEncoder<Vec<u8, N>>;
+
 const PROTOCOL_MAJOR: u16 = 21;
 const PROTOCOL_MINOR: u16 = 171;
 
+/// The record header has 4 bits for the local message type.
+const LOCAL_MESSAGE_TYPES: usize = 16;
+/// Distinct definitions a single file can hold.
+const MAX_DEFINITIONS: usize = 32;
+/// Fields a single definition can hold.
+const MAX_FIELDS: usize = 32;
+/// Developer fields a single definition can hold.
+const MAX_DEVELOPER_FIELDS: usize = 8;
+
+/// Record header bit flagging developer fields in a Definition Message.
+const DEVELOPER_DATA_HEADER: u8 = 32;
+/// Field Description strings are padded to a fixed size, so every
+/// description shares a single definition.
+const FIELD_NAME_SIZE: usize = 64;
+const UNITS_SIZE: usize = 16;
+
+/// Largest record, record header included. Records are assembled in a
+/// buffer of this size and written whole, so a failed write leaves no
+/// partial record behind.
+const MAX_RECORD_SIZE: usize = 1024;
+
+/// Timestamp field number, common to all messages.
+const TIMESTAMP_FIELD_NUMBER: u8 = 253;
+/// Record header bit flagging a compressed timestamp header.
+const COMPRESSED_TIMESTAMP_HEADER: u8 = 128;
+/// Compressed timestamp headers only have 2 bits for the local message type.
+const COMPRESSED_TIMESTAMP_LOCAL_MESSAGE_TYPES: usize = 4;
+/// Compressed timestamp headers have 5 bits for the time offset.
+const COMPRESSED_TIMESTAMP_MASK: u32 = 0x1F;
+
 impl<const N: usize> FitFile<N> {
-    pub fn new(file_type: FitFileType, ts: u32) -> Result<Self, FitError> {
+    pub fn new(file_type: FitFileType, time_created: FitDateTime) -> Result<Self, FitError> {
+        Self::with_options(file_type, time_created, FitFileOptions::default())
+    }
+
+    pub fn with_options(
+        file_type: FitFileType,
+        time_created: FitDateTime,
+        options: FitFileOptions,
+    ) -> Result<Self, FitError> {
+        Self::with_sink(Vec::new(), file_type, time_created, options)
+    }
+
+    /// Finalize the file, writing the data size, the CRCs, and returning the
+    /// file content. Nothing can be written afterwards, unless the file is
+    /// reopened.
+    ///
+    /// Calling it again returns the same content.
+    pub fn done(&mut self) -> Result<&[u8], FitError> {
+        self.finish()?;
+        Ok(&self.sink)
+    }
+}
+
+impl<S: FitSink> FitEncoder<S> {
+    /// Create a file writing to `sink`, starting with the header and the
+    /// File Id message.
+    pub fn with_sink(
+        sink: S,
+        file_type: FitFileType,
+        time_created: FitDateTime,
+        options: FitFileOptions,
+    ) -> Result<Self, FitError> {
         let mut fit_file = Self {
-            stream: Vec::new(),
-            arch: FitMessageArchitecture::LSB,
-            manufacturer: FitFileManufacturerType::Development,
+            sink,
+            data_size: 0,
+            crc: 0,
+            header_size: options.header_size,
+            arch: options.arch,
+            definitions: Vec::new(),
+            local_types: [None; LOCAL_MESSAGE_TYPES],
+            local_types_usage: [0; LOCAL_MESSAGE_TYPES],
+            usage: 0,
+            last_timestamp: None,
+            developers: 0,
+            finalized: false,
         };
-        fit_file.build_header().map_err(|e| FitError::Failed(e))?;
-        fit_file
-            .build_file_id(file_type, ts)
-            .map_err(|e| FitError::Failed(e))?;
+        fit_file.build_header()?;
+        fit_file.build_file_id(file_type, time_created, &options.file_id)?;
         Ok(fit_file)
     }
 
-    fn build_header(&mut self) -> Result<(), u8> {
-        // Header Size
-        self.stream.push(14)?;
+    /// Writes the header, as a placeholder until the data size is known.
+    fn build_header(&mut self) -> Result<(), FitError> {
+        let header = self.header();
+        self.sink.write(&header[..self.header_size as usize])
+    }
 
-        // Protocol Version
-        self.stream.push(4 << FitProtocolVersion::Version2 as u8)?;
+    /// Header for the records written so far. The CRC is only written in
+    /// standard headers.
+    fn header(&self) -> [u8; 14] {
+        let mut header = [0u8; 14];
+        // [0] Header Size
+        header[0] = self.header_size as u8;
 
-        let profile_version: u16 = PROTOCOL_MAJOR as u16 * 1000 + PROTOCOL_MINOR as u16;
-        // Profile Version
-        self.stream
-            .extend_from_slice(&profile_version.to_le_bytes())
-            .map_err(|_e| 0)?;
+        // [1] Protocol Version
+        header[1] = 4 << FitProtocolVersion::Version2 as u8;
 
-        // Data size (4 bytes)
-        self.stream
-            .extend_from_slice(&(0u32).to_le_bytes())
-            .map_err(|_e| 0)?;
+        let profile_version: u16 = PROTOCOL_MAJOR * 1000 + PROTOCOL_MINOR;
+        // [2:4] Profile Version
+        header[2..4].copy_from_slice(&profile_version.to_le_bytes());
 
-        // Data Type (.FIT)
-        self.stream.extend_from_slice(b".FIT").map_err(|_e| 0)?;
+        // [4:8] Data size
+        header[4..8].copy_from_slice(&(self.data_size as u32).to_le_bytes());
 
-        // CRC size (2 bytes)
-        self.stream.push(0)?;
-        self.stream.push(0)?;
+        // [8:12] Data Type (.FIT)
+        header[8..12].copy_from_slice(b".FIT");
 
-        Ok(())
+        // [12:14] Header CRC, over the header bytes [0:12]
+        let crc = header[..12]
+            .iter()
+            .fold(0, |crc, byte| crc_get16(crc, *byte));
+        header[12..14].copy_from_slice(&crc.to_le_bytes());
+
+        header
     }
 
     /// All FIT files must contain a single File Id message. The File Id
     /// message identifies the intent of the FIT file through the Type field.
     /// The File Id message should be the first message in the file.
-    fn build_file_id(
+    fn build_file_id<'a>(
         &mut self,
         file_type: FitFileType,
-        // The FIT Profile defines the date_time type as an uint32 that
-        // represents the number of seconds since midnight on December 31, 1989 UTC*.
-        // This date is often referred to as the FIT Epoch.
-        ts: u32,
-    ) -> Result<(), u8> {
-        self.define(
-            FitGlobalMessageType::FileId,
-            &[
-                FitFileIdFieldDefinition::Type,
-                FitFileIdFieldDefinition::Manufacturer,
-                FitFileIdFieldDefinition::TimeCreated,
-            ],
-        )?;
+        time_created: FitDateTime,
+        device: &FitFileId<'a>,
+    ) -> Result<(), FitError> {
+        let mut fields: Vec<FitSizedField<FitFileIdFieldDefinition>, 7> = Vec::new();
+        let mut values: Vec<(FitSizedField<FitFileIdFieldDefinition>, FitValue<'a>), 7> =
+            Vec::new();
+        let mut add = |field: FitSizedField<FitFileIdFieldDefinition>, value: FitValue<'a>| {
+            // At most one value per field, so they can't overflow.
+            let _ = fields.push(field);
+            let _ = values.push((field, value));
+        };
 
-        let mut buffer = [0u8; 7]; // 1 + 2 + 4 bytes
-        buffer[0] = file_type as u8;
-        buffer[1..3].copy_from_slice(&(self.manufacturer as u16).to_le_bytes());
-        buffer[3..7].copy_from_slice(&(ts).to_le_bytes());
-        self.push(&buffer)?;
+        // Fields in the order of their field number.
+        add(
+            FitFileIdFieldDefinition::Type.into(),
+            FitValue::Enum(file_type as u8),
+        );
+        add(
+            FitFileIdFieldDefinition::Manufacturer.into(),
+            u16::from(device.manufacturer).into(),
+        );
+        if let Some(product) = device.product {
+            add(FitFileIdFieldDefinition::Product.into(), product.into());
+        }
+        if let Some(serial_number) = device.serial_number {
+            add(
+                FitFileIdFieldDefinition::SerialNumber.into(),
+                FitValue::Uint32z(serial_number),
+            );
+        }
+        add(
+            FitFileIdFieldDefinition::TimeCreated.into(),
+            time_created.into(),
+        );
+        if let Some(number) = device.number {
+            add(FitFileIdFieldDefinition::Number.into(), number.into());
+        }
+        if let Some(product_name) = device.product_name {
+            let product_name = truncate(product_name, u8::MAX as usize - 1);
+            add(
+                FitFileIdFieldDefinition::ProductName.string(product_name.len() as u8 + 1)?,
+                product_name.into(),
+            );
+        }
 
-        Ok(())
+        let file_id = self.define(FitGlobalMessageType::FileId, &fields)?;
+        self.push_values(file_id, &values)
     }
 
-    fn build_record_header(&mut self, msg_type: FitMessageType) -> Result<(), u8> {
-        self.stream.push(msg_type as u8)?;
+    /// Writes record bytes to the sink, updating the data size and CRC.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), FitError> {
+        self.sink.write(bytes)?;
+        self.crc = bytes
+            .iter()
+            .fold(self.crc, |crc, byte| crc_get16(crc, *byte));
+        self.data_size += bytes.len();
         Ok(())
     }
 
-    fn build_message_definition_content<T: FitFieldDefinition>(
-        &mut self,
-        gmsg_num: FitGlobalMessageType,
-        fields_def: &[T],
-    ) -> Result<(), u8> {
+    /// Encodes the Definition Message record of a definition into `record`,
+    /// returning its size.
+    fn encode_definition(&self, definition: usize, header: u8, record: &mut Record) -> usize {
+        let definition = &self.definitions[definition];
+
+        record.push(&[FitMessageType::DefinitionMessage as u8 | header]);
         // [0] Reserved
-        self.stream.push(0)?;
+        record.push(&[0]);
         // [1] Architecture LSB (0) or MSB (1)
-        // Note: LSB only.
-        self.stream.push(self.arch as u8)?;
+        record.push(&[self.arch as u8]);
 
         // [2:4] Global Message Number (0:65535 Unique)
-        self.stream
-            .extend_from_slice(&(gmsg_num as u16).to_le_bytes())
-            .map_err(|_e| 0)?;
+        record.push(&arch_bytes(
+            self.arch,
+            definition.global_msg_num.to_le_bytes(),
+        ));
 
         // [4] Number of fields in the Data Message
-        self.stream.push(fields_def.len() as u8)?;
+        record.push(&[definition.fields.len() as u8]);
 
         // [5:N] Field Definition
-        for def in fields_def {
-            self.build_field_definition_content(def)?;
+        for field in &definition.fields {
+            record.push(field);
         }
 
-        Ok(())
+        // [N] Number of developer fields, [N+1:M] Developer Field Definition
+        if !definition.developer_fields.is_empty() {
+            record.push(&[definition.developer_fields.len() as u8]);
+            for field in &definition.developer_fields {
+                record.push(field);
+            }
+        }
+        record.len
     }
 
-    fn build_field_definition_content<T: FitFieldDefinition>(
-        &mut self,
-        field: &T,
-    ) -> Result<(), u8> {
-        self.stream
-            .extend_from_slice(&field.get())
-            .map_err(|_e| 0)?;
-        Ok(())
+    /// Assigns a local message type to a definition, reusing a free one or
+    /// evicting the least recently used, and emits the Definition Message.
+    fn build_definition(&mut self, definition: usize) -> Result<u8, FitError> {
+        let local_types = if self.definitions[definition].compressed_timestamp {
+            COMPRESSED_TIMESTAMP_LOCAL_MESSAGE_TYPES
+        } else {
+            LOCAL_MESSAGE_TYPES
+        };
+
+        let local_type = match self.local_types[..local_types]
+            .iter()
+            .position(|t| t.is_none())
+        {
+            Some(free) => free,
+            None => {
+                let mut lru = 0;
+                for n in 1..local_types {
+                    if self.local_types_usage[n] < self.local_types_usage[lru] {
+                        lru = n;
+                    }
+                }
+                lru
+            }
+        };
+
+        let mut header = local_type as u8;
+        if !self.definitions[definition].developer_fields.is_empty() {
+            header |= DEVELOPER_DATA_HEADER;
+        }
+        // The definition is only active once the whole record is written.
+        let mut record = Record::new();
+        let len = self.encode_definition(definition, header, &mut record);
+        self.write(&record.bytes[..len])?;
+
+        if let Some(evicted) = self.local_types[local_type] {
+            self.definitions[evicted as usize].local_type = None;
+        }
+        self.local_types[local_type] = Some(definition as u8);
+        self.definitions[definition].local_type = Some(local_type as u8);
+        Ok(local_type as u8)
     }
 
-    fn crc_get16(&self, crc: u16, byte: u8) -> u16 {
-        const CRC_TABLE: [u16; 16] = [
-            0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800,
-            0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
-        ];
+    /// Returns the local message type holding the definition, emitting the
+    /// definition again if its local message type was reused.
+    fn local_type(&mut self, definition: usize) -> Result<u8, FitError> {
+        let local_type = self.active_local_type(definition)?;
+        self.mark_used(local_type);
+        Ok(local_type)
+    }
 
-        let tmp = CRC_TABLE[(crc & 0xF) as usize];
-        let mut crc = (crc >> 4) & 0x0FFF;
-        crc = crc ^ tmp ^ CRC_TABLE[(byte & 0xF) as usize];
+    /// Returns the local message type holding the definition, emitting the
+    /// definition again if needed, without marking it used.
+    fn active_local_type(&mut self, definition: usize) -> Result<u8, FitError> {
+        match self.definitions[definition].local_type {
+            Some(local_type) => Ok(local_type),
+            None => self.build_definition(definition),
+        }
+    }
 
-        let tmp = CRC_TABLE[(crc & 0xF) as usize];
-        crc = (crc >> 4) & 0x0FFF;
-        crc ^ tmp ^ CRC_TABLE[((byte >> 4) & 0xF) as usize]
+    fn mark_used(&mut self, local_type: u8) {
+        self.usage += 1;
+        self.local_types_usage[local_type as usize] = self.usage;
     }
 
-    pub fn done(&mut self) -> Result<&[u8], FitError> {
-        let size = self.size() - 14;
-        for n in 0..4 {
-            self.stream[n + 4] = ((size >> (n * 8)) & 0xff) as u8;
+    fn check_not_finalized(&self) -> Result<(), FitError> {
+        if self.finalized {
+            return Err(FitError::AlreadyFinalized);
         }
+        Ok(())
+    }
+
+    /// Finalize the file, patching the header with the data size and its
+    /// CRC, and appending the file CRC. Nothing can be written afterwards,
+    /// unless the file is reopened.
+    ///
+    /// Finishing a finalized file does nothing.
+    pub fn finish(&mut self) -> Result<(), FitError> {
+        if self.finalized {
+            return Ok(());
+        }
+
+        let header = self.header();
+        let header = &header[..self.header_size as usize];
+        self.sink.patch(0, header)?;
 
-        let mut crc = 0u16;
-        for byte in &self.stream {
-            crc = self.crc_get16(crc, *byte);
+        // The CRC is linear, so the CRC of the file is the CRC of the header
+        // carried over as many zeros as there are data bytes, combined with
+        // the CRC of the data.
+        let mut crc = header.iter().fold(0, |crc, byte| crc_get16(crc, *byte));
+        for _ in 0..self.data_size {
+            crc = crc_get16(crc, 0);
         }
+        crc ^= self.crc;
 
         // [N-2, N-1] CRC
-        self.stream
-            .extend_from_slice(&crc.to_le_bytes())
-            .map_err(|_e| FitError::Failed(0))?;
+        self.sink.write(&crc.to_le_bytes())?;
+        self.finalized = true;
+
+        Ok(())
+    }
+
+    /// Reopen a finalized file to keep appending records, truncating its
+    /// file CRC. The header and the CRC are written again by the next
+    /// [`FitEncoder::finish`].
+    ///
+    /// Reopening a file that isn't finalized does nothing.
+    pub fn reopen(&mut self) -> Result<(), FitError> {
+        if !self.finalized {
+            return Ok(());
+        }
+        self.sink.truncate(self.size() - 2)?;
+        self.finalized = false;
+        Ok(())
+    }
 
-        Ok(&self.stream)
+    pub fn is_finalized(&self) -> bool {
+        self.finalized
     }
 
+    fn find_or_add_definition(&mut self, definition: FitDefinition) -> Result<usize, FitError> {
+        if let Some(n) = self.definitions.iter().position(|d| {
+            d.global_msg_num == definition.global_msg_num
+                && d.fields == definition.fields
+                && d.developer_fields == definition.developer_fields
+                && d.compressed_timestamp == definition.compressed_timestamp
+        }) {
+            return Ok(n);
+        }
+
+        self.definitions
+            .push(definition)
+            .map_err(|_e| FitError::TooManyDefinitions)?;
+        Ok(self.definitions.len() - 1)
+    }
+
+    /// Define a message and emit its Definition Message.
+    ///
+    /// Defining the same message and fields again returns the same handle and
+    /// only emits the Definition Message if its local message type was reused.
     pub fn define<T: FitFieldDefinition>(
         &mut self,
         global_msg_num: FitGlobalMessageType,
         fields_def: &[T],
-    ) -> Result<(), u8> {
-        self.build_record_header(FitMessageType::DefinitionMessage)?;
-        self.build_message_definition_content(global_msg_num, fields_def)?;
-        Ok(())
+    ) -> Result<FitLocalMessage, FitError> {
+        self.define_with_developer_fields(global_msg_num, fields_def, &[])
+    }
+
+    /// Define a message with developer fields after its native fields.
+    pub fn define_with_developer_fields<T: FitFieldDefinition>(
+        &mut self,
+        global_msg_num: FitGlobalMessageType,
+        fields_def: &[T],
+        developer_fields_def: &[FitDeveloperField],
+    ) -> Result<FitLocalMessage, FitError> {
+        let mut fields: Vec<[u8; 3], MAX_FIELDS> = Vec::new();
+        for def in fields_def {
+            fields
+                .push(def.get())
+                .map_err(|_e| FitError::TooManyFields)?;
+        }
+        let mut developer_fields: Vec<[u8; 3], MAX_DEVELOPER_FIELDS> = Vec::new();
+        for def in developer_fields_def {
+            developer_fields
+                .push(def.get())
+                .map_err(|_e| FitError::TooManyFields)?;
+        }
+        self.define_fields(global_msg_num, &fields, &developer_fields)
     }
 
-    /// Push any data as it was defined by a previous Definition Message.
-    pub fn push(&mut self, data: &[u8]) -> Result<(), u8> {
-        self.build_record_header(FitMessageType::DataMessage)?;
-        self.stream.extend_from_slice(data).map_err(|_e| 0)?;
+    /// Define a message whose Data Messages use compressed timestamp headers.
+    ///
+    /// The fields must include the Timestamp field. Data is pushed with the
+    /// timestamp as usual, and whenever it is less than 32 seconds after the
+    /// last timestamp written, it is moved into the record header instead.
+    /// Otherwise the message is written with its full timestamp.
+    pub fn define_compressed<T: FitFieldDefinition>(
+        &mut self,
+        global_msg_num: FitGlobalMessageType,
+        fields_def: &[T],
+    ) -> Result<FitLocalMessage, FitError> {
+        let msg = self.define(global_msg_num, fields_def)?;
+        let definition = msg.definition as usize;
+        if self.definitions[definition].compressed.is_some() {
+            return Ok(msg);
+        }
+        if self.definitions[definition].timestamp_offset().is_none() {
+            return Err(FitError::NoTimestampField);
+        }
+
+        let mut fields = Vec::new();
+        for field in &self.definitions[definition].fields {
+            if field[0] != TIMESTAMP_FIELD_NUMBER {
+                fields.push(*field).map_err(|_e| FitError::TooManyFields)?;
+            }
+        }
+        let compressed = self.find_or_add_definition(FitDefinition {
+            global_msg_num: self.definitions[definition].global_msg_num,
+            fields,
+            developer_fields: self.definitions[definition].developer_fields.clone(),
+            local_type: None,
+            compressed_timestamp: true,
+            compressed: None,
+        })?;
+        self.definitions[definition].compressed = Some(compressed as u8);
+        Ok(msg)
+    }
+
+    /// Starts the record of a Data Message with its record header, using a
+    /// compressed timestamp header if the definition allows it and the
+    /// timestamp is close enough to the last one. Emits the Definition
+    /// Message first if its local message type was reused.
+    fn data_record_header(
+        &mut self,
+        definition: usize,
+        ts: Option<u32>,
+    ) -> Result<DataRecord, FitError> {
+        let compressed = self.definitions[definition].compressed;
+        let mut record = Record::new();
+
+        match (compressed, ts, self.last_timestamp) {
+            // The time offset rolls over every 32 seconds, so it can only
+            // express timestamps less than 32 seconds after the last one.
+            (Some(compressed), Some(ts), Some(last))
+                if ts.wrapping_sub(last) <= COMPRESSED_TIMESTAMP_MASK =>
+            {
+                let local_type = self.active_local_type(compressed as usize)?;
+                let time_offset = (ts & COMPRESSED_TIMESTAMP_MASK) as u8;
+                record.push(&[COMPRESSED_TIMESTAMP_HEADER | local_type << 5 | time_offset]);
+                Ok(DataRecord {
+                    record,
+                    local_type,
+                    compressed: true,
+                })
+            }
+            _ => {
+                let local_type = self.active_local_type(definition)?;
+                record.push(&[FitMessageType::DataMessage as u8 | local_type]);
+                Ok(DataRecord {
+                    record,
+                    local_type,
+                    compressed: false,
+                })
+            }
+        }
+    }
+
+    /// Writes a whole Data Message record in one write. Its local message
+    /// type and timestamp only count once the write succeeded.
+    fn write_data_record(&mut self, data: &DataRecord, ts: Option<u32>) -> Result<(), FitError> {
+        self.write(&data.record.bytes[..data.record.len])?;
+        self.mark_used(data.local_type);
+        if ts.is_some() {
+            self.last_timestamp = ts;
+        }
         Ok(())
     }
 
-    pub fn size(&mut self) -> usize {
-        self.stream.len()
+    fn definition(&self, msg: FitLocalMessage) -> Result<usize, FitError> {
+        let definition = msg.definition as usize;
+        if definition >= self.definitions.len() {
+            return Err(FitError::NoActiveDefinition);
+        }
+        Ok(definition)
+    }
+
+    /// Push any data as it was defined by the Definition Message of `msg`.
+    ///
+    /// Multi-byte values must be in the byte order of the file architecture,
+    /// and the data must have the size of the definition.
+    pub fn push(&mut self, msg: FitLocalMessage, data: &[u8]) -> Result<(), FitError> {
+        self.check_not_finalized()?;
+        let definition = self.definition(msg)?;
+        let expected = self.definitions[definition].data_size();
+        if data.len() != expected {
+            return Err(FitError::DataSizeMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        let offset = self.definitions[definition].timestamp_offset();
+        let ts =
+            offset.map(|offset| u32::from_le_bytes(le_bytes(self.arch, &data[offset..offset + 4])));
+
+        let mut record = self.data_record_header(definition, ts)?;
+        match (record.compressed, offset) {
+            (true, Some(offset)) => {
+                record.record.push(&data[..offset]);
+                record.record.push(&data[offset + 4..]);
+            }
+            _ => record.record.push(data),
+        }
+        self.write_data_record(&record, ts)
+    }
+
+    /// Push typed values, one per field of the Definition Message of `msg`.
+    ///
+    /// The fields must be given in the order they were defined, and each
+    /// value must match the base type of its field. Values are encoded in
+    /// the byte order of the file architecture.
+    pub fn push_values<T: FitFieldDefinition>(
+        &mut self,
+        msg: FitLocalMessage,
+        values: &[(T, FitValue)],
+    ) -> Result<(), FitError> {
+        self.push_values_with_developer_fields(msg, values, &[])
+    }
+
+    /// Push typed values for native fields, then for developer fields.
+    pub fn push_values_with_developer_fields<T: FitFieldDefinition>(
+        &mut self,
+        msg: FitLocalMessage,
+        values: &[(T, FitValue)],
+        developer_values: &[(FitDeveloperField, FitValue)],
+    ) -> Result<(), FitError> {
+        self.check_not_finalized()?;
+        let definition = self.definition(msg)?;
+        let def = &self.definitions[definition];
+
+        for index in 0..values.len().max(def.fields.len()) {
+            let (Some((field, value)), Some(def)) = (values.get(index), def.fields.get(index))
+            else {
+                return Err(FitError::FieldMismatch { index });
+            };
+            if !field_matches(field.get(), *def) || !value.fits(field.base_type(), def[1] as usize)
+            {
+                return Err(FitError::FieldMismatch { index });
+            }
+        }
+        for n in 0..developer_values.len().max(def.developer_fields.len()) {
+            // Developer fields are indexed after the native fields.
+            let index = def.fields.len() + n;
+            let (Some((field, value)), Some(def)) =
+                (developer_values.get(n), def.developer_fields.get(n))
+            else {
+                return Err(FitError::FieldMismatch { index });
+            };
+            if !field_matches(field.get(), *def) || !value.fits(field.base_type(), def[1] as usize)
+            {
+                return Err(FitError::FieldMismatch { index });
+            }
+        }
+
+        let ts = values.iter().find_map(|(field, value)| match value {
+            FitValue::Uint32(ts) if field.field_number() == TIMESTAMP_FIELD_NUMBER => Some(*ts),
+            _ => None,
+        });
+        let mut record = self.data_record_header(definition, ts)?;
+
+        // Values are sized by the definition, for strings and arrays.
+        for (index, (field, value)) in values.iter().enumerate() {
+            if record.compressed && field.field_number() == TIMESTAMP_FIELD_NUMBER {
+                continue;
+            }
+            let size = self.definitions[definition].fields[index][1];
+            let buffer = record.record.reserve(size as usize);
+            encode_value(field.base_type(), value, self.arch, buffer);
+        }
+        for (index, (field, value)) in developer_values.iter().enumerate() {
+            let size = self.definitions[definition].developer_fields[index][1];
+            let buffer = record.record.reserve(size as usize);
+            encode_value(field.base_type(), value, self.arch, buffer);
+        }
+        self.write_data_record(&record, ts)
+    }
+
+    /// Emit a Developer Data Id message, identifying the application that
+    /// describes developer fields.
+    ///
+    /// Fails with [`FitError::TooManyDevelopers`] past 255 developers.
+    pub fn add_developer(
+        &mut self,
+        application_id: [u8; 16],
+        application_version: u32,
+    ) -> Result<FitDeveloper, FitError> {
+        // 0xFF is the invalid developer data index.
+        if self.developers == u8::MAX {
+            return Err(FitError::TooManyDevelopers);
+        }
+        let developer = FitDeveloper {
+            developer_data_index: self.developers,
+        };
+
+        let msg = self.define_fields(
+            FitGlobalMessageType::DeveloperDataId,
+            &[
+                FitDeveloperDataIdFieldDefinition::ApplicationId
+                    .array(16)?
+                    .get(),
+                FitDeveloperDataIdFieldDefinition::ApplicationVersion.get(),
+                FitDeveloperDataIdFieldDefinition::DeveloperDataIndex.get(),
+            ],
+            &[],
+        )?;
+
+        let mut buffer = [0u8; 21]; // 16 + 4 + 1 bytes
+        buffer[0..16].copy_from_slice(&application_id);
+        buffer[16..20].copy_from_slice(&arch_bytes(self.arch, application_version.to_le_bytes()));
+        buffer[20] = developer.developer_data_index;
+        self.push(msg, &buffer)?;
+
+        self.developers += 1;
+        Ok(developer)
+    }
+
+    /// Emit a Field Description message, describing a developer field.
+    ///
+    /// The name is truncated to 63 bytes and the units to 15 bytes.
+    pub fn describe_field(
+        &mut self,
+        developer: FitDeveloper,
+        field_number: u8,
+        base_type: FitBaseType,
+        name: &str,
+        units: &str,
+    ) -> Result<FitDeveloperField, FitError> {
+        if developer.developer_data_index >= self.developers {
+            return Err(FitError::UnknownDeveloper);
+        }
+
+        let msg = self.define_fields(
+            FitGlobalMessageType::FieldDescription,
+            &[
+                FitFieldDescriptionFieldDefinition::DeveloperDataIndex.get(),
+                FitFieldDescriptionFieldDefinition::FieldDefinitionNumber.get(),
+                FitFieldDescriptionFieldDefinition::FitBaseTypeId.get(),
+                FitFieldDescriptionFieldDefinition::FieldName
+                    .string(FIELD_NAME_SIZE as u8)?
+                    .get(),
+                FitFieldDescriptionFieldDefinition::Units
+                    .string(UNITS_SIZE as u8)?
+                    .get(),
+            ],
+            &[],
+        )?;
+
+        let mut buffer = [0u8; 3 + FIELD_NAME_SIZE + UNITS_SIZE];
+        buffer[0] = developer.developer_data_index;
+        buffer[1] = field_number;
+        buffer[2] = base_type as u8;
+        let name = truncate(name, FIELD_NAME_SIZE - 1);
+        buffer[3..3 + name.len()].copy_from_slice(name.as_bytes());
+        let units = truncate(units, UNITS_SIZE - 1);
+        buffer[3 + FIELD_NAME_SIZE..3 + FIELD_NAME_SIZE + units.len()]
+            .copy_from_slice(units.as_bytes());
+        self.push(msg, &buffer)?;
+
+        Ok(FitDeveloperField {
+            developer_data_index: developer.developer_data_index,
+            field_number,
+            base_type,
+            size: base_type.size() as u8,
+        })
+    }
+
+    /// Define a message from raw field definitions.
+    fn define_fields(
+        &mut self,
+        global_msg_num: FitGlobalMessageType,
+        fields_def: &[[u8; 3]],
+        developer_fields_def: &[[u8; 3]],
+    ) -> Result<FitLocalMessage, FitError> {
+        self.check_not_finalized()?;
+        let definition = FitDefinition {
+            global_msg_num: global_msg_num as u16,
+            fields: Vec::from_slice(fields_def).map_err(|_e| FitError::TooManyFields)?,
+            developer_fields: Vec::from_slice(developer_fields_def)
+                .map_err(|_e| FitError::TooManyFields)?,
+            local_type: None,
+            compressed_timestamp: false,
+            compressed: None,
+        };
+        if 1 + definition.data_size() > MAX_RECORD_SIZE {
+            return Err(FitError::RecordTooLarge {
+                size: 1 + definition.data_size(),
+            });
+        }
+        let definition = self.find_or_add_definition(definition)?;
+        self.local_type(definition)?;
+        Ok(FitLocalMessage {
+            definition: definition as u8,
+        })
+    }
+
+    /// Bytes written to the sink so far.
+    pub fn size(&self) -> usize {
+        let crc = if self.finalized { 2 } else { 0 };
+        self.header_size as usize + self.data_size + crc
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn into_sink(self) -> S {
+        self.sink
     }
 }
+
+/// Whether a field matches a field definition, whatever their sizes, as
+/// strings and arrays are sized by the definition.
+fn field_matches(field: [u8; 3], def: [u8; 3]) -> bool {
+    field[0] == def[0] && field[2] == def[2]
+}
+
+/// Encodes a value of a field of `base_type`. Missing values are written
+/// as the invalid value of the field, which differs between types of the
+/// same size, such as `uint8` and `uint8z`.
+fn encode_value(
+    base_type: FitBaseType,
+    value: &FitValue,
+    arch: FitMessageArchitecture,
+    buffer: &mut [u8],
+) {
+    match value {
+        FitValue::Invalid(_) => base_type.encode_invalid(arch, buffer),
+        _ => value.encode(arch, buffer),
+    }
+}
+
+/// String truncated to at most `len` bytes on a char boundary.
+pub(crate) fn truncate(s: &str, len: usize) -> &str {
+    let mut len = len.min(s.len());
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    &s[..len]
+}
+
+pub(crate) fn crc_get16(crc: u16, byte: u8) -> u16 {
+    const CRC_TABLE: [u16; 16] = [
+        0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800,
+        0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+    ];
+
+    let tmp = CRC_TABLE[(crc & 0xF) as usize];
+    let mut crc = (crc >> 4) & 0x0FFF;
+    crc = crc ^ tmp ^ CRC_TABLE[(byte & 0xF) as usize];
+
+    let tmp = CRC_TABLE[(crc & 0xF) as usize];
+    crc = (crc >> 4) & 0x0FFF;
+    crc ^ tmp ^ CRC_TABLE[((byte >> 4) & 0xF) as usize]
+}
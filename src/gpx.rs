@@ -0,0 +1,233 @@
+use roxmltree::{Document, Node};
+
+use crate::{
+    activity::{ActivityRecorder, ActivitySample},
+    course::{CourseBuilder, CourseError, CourseTrackPoint},
+    fit::{FitEncoder, FitError, FitFileOptions},
+    position::FitPosition,
+    time::FitDateTime,
+    types::{FitFileType, FitSportType, FitSubSportType},
+};
+
+#[derive(Debug, PartialEq)]
+pub enum GpxError {
+    /// Text isn't well-formed XML.
+    Xml(roxmltree::Error),
+    /// Document root isn't a `gpx` element.
+    NotGpx,
+    /// Point without a valid `lat` and `lon`.
+    InvalidPosition,
+    /// Activity point without a `time`.
+    MissingTime,
+    /// Track has no points.
+    NoPoints,
+    Course(CourseError),
+    Fit(FitError),
+}
+
+/// Point of a GPX track or route, with the Garmin TrackPointExtension
+/// readings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpxPoint {
+    pub position: FitPosition,
+    /// Elevation in m.
+    pub altitude: Option<f32>,
+    pub time: Option<FitDateTime>,
+    /// Heart rate in bpm, from `hr`.
+    pub heart_rate: Option<u8>,
+    /// Cadence in rpm, from `cad`.
+    pub cadence: Option<u8>,
+    /// Air temperature in degrees Celsius, from `atemp`.
+    pub temperature: Option<i8>,
+}
+
+/// Track or route of a GPX 1.1 file. The segments of a track are joined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpxTrack {
+    pub name: Option<String>,
+    pub points: Vec<GpxPoint>,
+}
+
+impl GpxTrack {
+    /// Tracks, `trk`, then routes, `rte`, of a GPX document.
+    pub fn parse(gpx: &str) -> Result<Vec<Self>, GpxError> {
+        let document = Document::parse(gpx).map_err(GpxError::Xml)?;
+        let root = document.root_element();
+        if root.tag_name().name() != "gpx" {
+            return Err(GpxError::NotGpx);
+        }
+
+        let mut tracks = Vec::new();
+        for (element, point) in [("trk", "trkpt"), ("rte", "rtept")] {
+            for node in children(root, element) {
+                let points = node
+                    .descendants()
+                    .filter(|node| node.tag_name().name() == point)
+                    .map(GpxPoint::parse)
+                    .collect::<Result<_, _>>()?;
+                tracks.push(Self {
+                    name: child(node, "name")
+                        .and_then(|name| name.text())
+                        .map(String::from),
+                    points,
+                });
+            }
+        }
+        Ok(tracks)
+    }
+
+    /// Course file of the track, with a virtual partner starting at
+    /// `start` and riding at `speed` m/s. Times of the points are ignored.
+    pub fn to_course(
+        &self,
+        sport: FitSportType,
+        start: FitDateTime,
+        speed: f32,
+    ) -> Result<Vec<u8>, GpxError> {
+        if self.points.is_empty() {
+            return Err(GpxError::NoPoints);
+        }
+        let track: Vec<_> = self
+            .points
+            .iter()
+            .map(|point| CourseTrackPoint {
+                position: point.position,
+                altitude: point.altitude,
+            })
+            .collect();
+        let name = self.name.as_deref().unwrap_or_default();
+
+        let mut fit = FitEncoder::with_sink(
+            Vec::new(),
+            FitFileType::Course,
+            start,
+            FitFileOptions::default(),
+        )?;
+        CourseBuilder::new(name, sport, &track)
+            .speed(speed)
+            .write(&mut fit, start)?;
+        fit.finish()?;
+        Ok(fit.into_sink())
+    }
+
+    /// Activity file of the track, with a Record per point. Every point
+    /// needs a time.
+    ///
+    /// Distances are cumulative along the track, and speeds are those
+    /// between consecutive points.
+    pub fn to_activity(
+        &self,
+        sport: FitSportType,
+        sub_sport: FitSubSportType,
+    ) -> Result<Vec<u8>, GpxError> {
+        let first = self.points.first().ok_or(GpxError::NoPoints)?;
+        let start = first.time.ok_or(GpxError::MissingTime)?;
+
+        let fit = FitEncoder::with_sink(
+            Vec::new(),
+            FitFileType::Activity,
+            start,
+            FitFileOptions::default(),
+        )?;
+        let mut recorder = ActivityRecorder::start(fit, sport, sub_sport, start)?;
+        let mut previous: Option<(FitPosition, FitDateTime)> = None;
+        let mut distance = 0.0;
+        for point in &self.points {
+            let timestamp = point.time.ok_or(GpxError::MissingTime)?;
+            let mut speed = None;
+            if let Some((position, time)) = previous {
+                let segment = position.distance(&point.position).unwrap_or(0.0);
+                distance += segment;
+                let seconds = timestamp.seconds().saturating_sub(time.seconds());
+                if seconds > 0 {
+                    speed = Some((segment / seconds as f64) as f32);
+                }
+            }
+            previous = Some((point.position, timestamp));
+
+            recorder.record(&ActivitySample {
+                timestamp,
+                position: Some(point.position),
+                altitude: point.altitude,
+                distance: Some(distance as f32),
+                speed,
+                heart_rate: point.heart_rate,
+                cadence: point.cadence,
+                power: None,
+                temperature: point.temperature,
+            })?;
+        }
+        let end = previous.map_or(start, |(_, time)| time);
+        Ok(recorder.finish(end)?.into_sink())
+    }
+}
+
+impl GpxPoint {
+    fn parse(node: Node) -> Result<Self, GpxError> {
+        let degrees = |name| node.attribute(name)?.trim().parse::<f64>().ok();
+        let position = degrees("lat")
+            .zip(degrees("lon"))
+            .and_then(|(lat, long)| FitPosition::from_degrees(lat, long))
+            .ok_or(GpxError::InvalidPosition)?;
+
+        // Readings of the TrackPointExtension, in any of its versions.
+        let extension = |name| {
+            child(node, "extensions")?
+                .descendants()
+                .find(|node| node.tag_name().name() == name)?
+                .text()?
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+        };
+        Ok(Self {
+            position,
+            altitude: text(node, "ele").map(|ele| ele as f32),
+            time: child(node, "time")
+                .and_then(|time| time.text())
+                .and_then(|time| FitDateTime::from_iso8601(time.trim())),
+            heart_rate: extension("hr").and_then(|hr| u8::try_from(hr.round() as i64).ok()),
+            cadence: extension("cad").and_then(|cad| u8::try_from(cad.round() as i64).ok()),
+            temperature: extension("atemp")
+                .and_then(|atemp| i8::try_from(atemp.round() as i64).ok()),
+        })
+    }
+}
+
+impl From<FitError> for GpxError {
+    fn from(error: FitError) -> Self {
+        Self::Fit(error)
+    }
+}
+
+impl From<CourseError> for GpxError {
+    fn from(error: CourseError) -> Self {
+        match error {
+            CourseError::Fit(error) => Self::Fit(error),
+            error => Self::Course(error),
+        }
+    }
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |node| node.tag_name().name() == name)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+/// Finite number in the text of the child element `name`.
+fn text(node: Node, name: &'static str) -> Option<f64> {
+    child(node, name)?
+        .text()?
+        .trim()
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite())
+}
@@ -1,24 +1,86 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+pub mod activity;
+pub mod course;
+pub mod decode;
+#[cfg(feature = "std")]
+pub mod export;
 pub mod fit;
+#[cfg(feature = "gpx")]
+pub mod gpx;
+pub mod position;
 pub mod profile;
+pub mod sink;
+pub mod time;
 pub mod types;
+pub mod value;
+pub mod workout;
 
-pub use fit::{FitError, FitFile, FitMessageArchitecture, FitProtocolVersion};
+pub use activity::{ActivityRecorder, ActivitySample};
+pub use course::{CourseBuilder, CourseError, CoursePoint, CourseTrackPoint};
+pub use decode::{
+    FitDataField, FitDataFields, FitDataMessage, FitDecodeError, FitDecoder, FitDefinitionField,
+    FitDefinitionMessage, FitDeveloperDataField, FitHeader, FitRecord,
+};
+#[cfg(feature = "std")]
+pub use export::{ExportLap, ExportTrack};
+pub use fit::{
+    FitDeveloper, FitDeveloperField, FitEncoder, FitError, FitFile, FitFileId, FitFileOptions,
+    FitHeaderSize, FitLocalMessage, FitMessageArchitecture, FitProtocolVersion,
+};
+#[cfg(feature = "gpx")]
+pub use gpx::{GpxError, GpxPoint, GpxTrack};
+pub use position::{FitPosition, FitSemicircles};
 pub use profile::*;
+pub use sink::FitSink;
+pub use time::{FitCalendarTime, FitDateTime, FitLocalDateTime, FIT_EPOCH_UNIX};
 pub use types::*;
+pub use value::{FitArray, FitValue};
+pub use workout::{
+    WorkoutBuilder, WorkoutDuration, WorkoutError, WorkoutHeartRate, WorkoutPower, WorkoutStep,
+    WorkoutTarget,
+};
 
 #[cfg(test)]
 mod tests {
-    use crate::{FitFile, FitFileType, FitGlobalMessageType, FitLapFieldDefinition};
+    use crate::{
+        ActivityRecorder, ActivitySample, CourseBuilder, CourseError, CoursePoint,
+        CourseTrackPoint, FitActivityFieldDefinition, FitArray, FitBaseType, FitCalendarTime,
+        FitCoursePointFieldDefinition, FitCoursePointType, FitDataMessage, FitDateTime,
+        FitDecodeError, FitDecoder, FitEncoder, FitError, FitEventFieldDefinition,
+        FitFieldDefinition, FitFile, FitFileId, FitFileIdFieldDefinition, FitFileManufacturerType,
+        FitFileOptions, FitFileType, FitGlobalMessageType, FitHeaderSize, FitLapFieldDefinition,
+        FitLocalDateTime, FitMessageArchitecture, FitPosition, FitRecord, FitRecordFieldDefinition,
+        FitSemicircles, FitSessionFieldDefinition, FitSink, FitSportType, FitSubSportType,
+        FitValue, FitWorkoutFieldDefinition, FitWorkoutStepDuration, FitWorkoutStepFieldDefinition,
+        WorkoutBuilder, WorkoutDuration, WorkoutError, WorkoutHeartRate, WorkoutStep,
+        WorkoutTarget,
+    };
+
+    /// Data messages of a file, only those of `global` if given.
+    fn data_messages(
+        buf: &[u8],
+        global: Option<FitGlobalMessageType>,
+    ) -> impl Iterator<Item = FitDataMessage<'_>> {
+        FitDecoder::new(buf)
+            .unwrap()
+            .filter_map(move |r| match r.unwrap() {
+                FitRecord::Data(message)
+                    if global.is_none() || message.global_message() == global =>
+                {
+                    Some(message)
+                }
+                _ => None,
+            })
+    }
 
     #[test]
     fn create_min_file() {
-        let fit = FitFile::<128>::new(FitFileType::Activity, 0);
+        let fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0));
         assert!(fit.is_ok());
 
         let mut fit_file = fit.unwrap();
-        assert_eq!(fit_file.size(), 33);
+        assert_eq!(fit_file.size(), 37);
 
         let buf = fit_file.done().unwrap();
         // Header 14 bytes. + File ID def 15 bytes + File Id Data 8 bytes + 2 bytes CRC  ✅
@@ -27,15 +89,131 @@ mod tests {
         assert_eq!(buf[4], 23);
     }
 
+    #[test]
+    fn create_header_crc() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let buf = fit.done().unwrap();
+
+        let crc = buf[..12]
+            .iter()
+            .fold(0, |crc, b| crate::fit::crc_get16(crc, *b));
+        assert_ne!(crc, 0);
+        assert_eq!(buf[12..14], crc.to_le_bytes());
+
+        let mut decoder = FitDecoder::new(buf).unwrap();
+        assert_eq!(decoder.header().crc, Some(crc));
+        assert!(decoder.all(|r| r.is_ok()));
+    }
+
+    #[test]
+    fn create_legacy_header_min_file() {
+        let options = FitFileOptions {
+            header_size: FitHeaderSize::Legacy,
+            ..Default::default()
+        };
+        let mut fit =
+            FitFile::<128>::with_options(FitFileType::Activity, FitDateTime::new(0), options)
+                .unwrap();
+        assert_eq!(fit.size(), 35);
+
+        let buf = fit.done().unwrap();
+        assert_eq!(buf[0], 12);
+        assert_eq!(buf[4], 23);
+        assert_eq!(buf.len(), 37);
+
+        let mut decoder = FitDecoder::new(buf).unwrap();
+        assert_eq!(decoder.header().crc, None);
+        assert!(decoder.all(|r| r.is_ok()));
+    }
+
+    #[test]
+    fn create_file_id() {
+        let options = FitFileOptions {
+            file_id: FitFileId {
+                manufacturer: FitFileManufacturerType::Decathlon,
+                product: Some(42),
+                serial_number: Some(123456),
+                product_name: Some("Watch"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut fit =
+            FitFile::<128>::with_options(FitFileType::Activity, FitDateTime::new(1000), options)
+                .unwrap();
+        let buf = fit.done().unwrap();
+
+        let mut decoder = FitDecoder::new(buf).unwrap();
+        let Some(Ok(FitRecord::Definition(definition))) = decoder.next() else {
+            panic!("expected File Id definition");
+        };
+        let numbers: [u8; 6] =
+            core::array::from_fn(|n| definition.fields().nth(n).unwrap().number());
+        assert_eq!(numbers, [0, 1, 2, 3, 4, 8]);
+
+        let Some(Ok(FitRecord::Data(file_id))) = decoder.next() else {
+            panic!("expected File Id message");
+        };
+        assert_eq!(
+            file_id.field(1).unwrap().value(),
+            Some(FitValue::Uint16(310))
+        );
+        assert_eq!(
+            file_id.field(2).unwrap().value(),
+            Some(FitValue::Uint16(42))
+        );
+        assert_eq!(
+            file_id.field(3).unwrap().value(),
+            Some(FitValue::Uint32z(123456))
+        );
+        assert_eq!(
+            file_id.field(4).unwrap().value(),
+            Some(FitValue::Uint32(1000))
+        );
+        assert_eq!(
+            file_id.field(8).unwrap().value(),
+            Some(FitValue::String("Watch"))
+        );
+        assert!(decoder.all(|r| r.is_ok()));
+    }
+
+    #[test]
+    fn manufacturer_conversions() {
+        use core::fmt::Write;
+
+        assert_eq!(
+            FitFileManufacturerType::from(1),
+            FitFileManufacturerType::Garmin
+        );
+        assert_eq!(u16::from(FitFileManufacturerType::WahooFitness), 32);
+        assert_eq!(
+            FitFileManufacturerType::from(u16::from(FitFileManufacturerType::Hammerhead)),
+            FitFileManufacturerType::Hammerhead
+        );
+        assert_eq!(
+            FitFileManufacturerType::from(9999),
+            FitFileManufacturerType::Unknown(9999)
+        );
+        assert_eq!(u16::from(FitFileManufacturerType::Unknown(9999)), 9999);
+        assert_eq!(FitFileManufacturerType::FourIiiis.name(), "4iiiis");
+
+        let mut name = heapless::String::<32>::new();
+        write!(name, "{}", FitFileManufacturerType::PolarElectro).unwrap();
+        assert_eq!(name, "polar_electro");
+        name.clear();
+        write!(name, "{}", FitFileManufacturerType::Unknown(9999)).unwrap();
+        assert_eq!(name, "unknown (9999)");
+    }
+
     #[test]
     fn create_def_activity_min_file() {
-        let mut fit = FitFile::<128>::new(FitFileType::Activity, 0).unwrap();
-        let result = fit.define(
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let lap = fit.define(
             FitGlobalMessageType::Lap,
             &[FitLapFieldDefinition::StartPositionLat],
         );
-        assert!(result.is_ok());
-        fit.push(&123u32.to_be_bytes()).unwrap(); // StartPositionLat is 123 LSB
+        assert!(lap.is_ok());
+        fit.push(lap.unwrap(), &123i32.to_le_bytes()).unwrap(); // StartPositionLat is 123 LSB
 
         let buf = fit.done().unwrap();
         // Header 14 bytes
@@ -46,4 +224,1735 @@ mod tests {
         // CRC 2 bytes
         assert_eq!(buf.len(), 53);
     }
+
+    #[test]
+    fn write_after_done() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define(
+                FitGlobalMessageType::Record,
+                &[FitRecordFieldDefinition::HeartRate],
+            )
+            .unwrap();
+        let size = fit.done().unwrap().len();
+
+        assert_eq!(fit.push(record, &[110]), Err(FitError::AlreadyFinalized));
+        assert_eq!(
+            fit.define(FitGlobalMessageType::Lap, &[FitLapFieldDefinition::Sport]),
+            Err(FitError::AlreadyFinalized)
+        );
+        assert_eq!(fit.size(), size);
+    }
+
+    #[test]
+    fn done_twice() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let first = heapless::Vec::<u8, 128>::from_slice(fit.done().unwrap()).unwrap();
+        assert_eq!(fit.done().unwrap(), &first[..]);
+        assert!(fit.is_finalized());
+    }
+
+    #[test]
+    fn reopen_to_append() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define(
+                FitGlobalMessageType::Record,
+                &[FitRecordFieldDefinition::HeartRate],
+            )
+            .unwrap();
+        fit.push(record, &[110]).unwrap();
+        fit.done().unwrap();
+
+        fit.reopen().unwrap();
+        assert!(!fit.is_finalized());
+        fit.push(record, &[111]).unwrap();
+        let buf = fit.done().unwrap();
+
+        let mut expected = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = expected
+            .define(
+                FitGlobalMessageType::Record,
+                &[FitRecordFieldDefinition::HeartRate],
+            )
+            .unwrap();
+        expected.push(record, &[110]).unwrap();
+        expected.push(record, &[111]).unwrap();
+        assert_eq!(buf, expected.done().unwrap());
+    }
+
+    #[test]
+    fn buffer_full() {
+        // Needed bytes are those of the whole File Id record.
+        assert_eq!(
+            FitFile::<32>::new(FitFileType::Activity, FitDateTime::new(0)),
+            Err(FitError::BufferFull { needed: 5 })
+        );
+
+        let mut fit = FitFile::<44>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define(
+                FitGlobalMessageType::Record,
+                &[] as &[FitRecordFieldDefinition],
+            )
+            .unwrap();
+        fit.push(record, &[]).unwrap();
+        assert_eq!(fit.done(), Err(FitError::BufferFull { needed: 2 }));
+    }
+
+    #[test]
+    fn buffer_full_keeps_records_whole() {
+        let mut fit = FitFile::<92>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define_compressed(
+                FitGlobalMessageType::Record,
+                &[
+                    FitRecordFieldDefinition::Timestamp,
+                    FitRecordFieldDefinition::HeartRate,
+                ],
+            )
+            .unwrap();
+        let event = fit
+            .define(
+                FitGlobalMessageType::Event,
+                &[
+                    FitEventFieldDefinition::Timestamp,
+                    FitEventFieldDefinition::Data,
+                    FitEventFieldDefinition::Event,
+                    FitEventFieldDefinition::EventType,
+                ],
+            )
+            .unwrap();
+        let mut data = [0u8; 5];
+        for (ts, bpm) in [(1000u32, 110), (1001, 111)] {
+            data[0..4].copy_from_slice(&ts.to_le_bytes());
+            data[4] = bpm;
+            fit.push(record, &data).unwrap();
+        }
+
+        // Room is left for a full Record and the file CRC, not for the
+        // 11 bytes of the Event record.
+        let result = fit.push_values(
+            event,
+            &[
+                (FitEventFieldDefinition::Timestamp, 1020u32.into()),
+                (FitEventFieldDefinition::Data, 0u32.into()),
+                (FitEventFieldDefinition::Event, FitValue::Enum(0)),
+                (FitEventFieldDefinition::EventType, FitValue::Enum(0)),
+            ],
+        );
+        assert_eq!(result, Err(FitError::BufferFull { needed: 3 }));
+
+        // 1040 is more than 31 seconds after the last timestamp written, so
+        // it isn't compressed.
+        data[0..4].copy_from_slice(&1040u32.to_le_bytes());
+        data[4] = 112;
+        fit.push(record, &data).unwrap();
+        let buf = fit.done().unwrap();
+
+        let timestamps = data_messages(buf, None).filter_map(|message| message.timestamp());
+        assert!(timestamps.map(|ts| ts.seconds()).eq([1000, 1001, 1040]));
+    }
+
+    /// Sink streaming bytes out, only keeping the header to patch it and
+    /// the last bytes written.
+    struct StreamSink {
+        header: [u8; 14],
+        last: [u8; 2],
+        size: usize,
+    }
+
+    impl FitSink for StreamSink {
+        fn write(&mut self, bytes: &[u8]) -> Result<(), FitError> {
+            for byte in bytes {
+                if self.size < self.header.len() {
+                    self.header[self.size] = *byte;
+                }
+                self.last = [self.last[1], *byte];
+                self.size += 1;
+            }
+            Ok(())
+        }
+
+        fn patch(&mut self, offset: usize, bytes: &[u8]) -> Result<(), FitError> {
+            self.header[offset..offset + bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stream_to_sink() {
+        for header_size in [FitHeaderSize::Standard, FitHeaderSize::Legacy] {
+            let options = FitFileOptions {
+                header_size,
+                ..Default::default()
+            };
+            let sink = StreamSink {
+                header: [0; 14],
+                last: [0; 2],
+                size: 0,
+            };
+            let mut fit =
+                FitEncoder::with_sink(sink, FitFileType::Activity, FitDateTime::new(0), options)
+                    .unwrap();
+            let mut mem =
+                FitFile::<128>::with_options(FitFileType::Activity, FitDateTime::new(0), options)
+                    .unwrap();
+            let record = fit
+                .define(
+                    FitGlobalMessageType::Record,
+                    &[FitRecordFieldDefinition::HeartRate],
+                )
+                .unwrap();
+            let mem_record = mem
+                .define(
+                    FitGlobalMessageType::Record,
+                    &[FitRecordFieldDefinition::HeartRate],
+                )
+                .unwrap();
+            for bpm in 100..110 {
+                fit.push(record, &[bpm]).unwrap();
+                mem.push(mem_record, &[bpm]).unwrap();
+            }
+            fit.finish().unwrap();
+            let buf = mem.done().unwrap();
+
+            let sink = fit.into_sink();
+            assert_eq!(sink.size, buf.len());
+            let size = header_size as usize;
+            assert_eq!(sink.header[..size], buf[..size]);
+            // The file CRC is streamed last, once the header is patched.
+            assert_eq!(sink.last, buf[buf.len() - 2..]);
+            assert!(FitDecoder::new(buf).unwrap().all(|r| r.is_ok()));
+        }
+    }
+
+    #[test]
+    fn too_many_fields() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let fields: [FitRecordFieldDefinition; 33] =
+            core::array::from_fn(|_| FitRecordFieldDefinition::HeartRate);
+        assert_eq!(
+            fit.define(FitGlobalMessageType::Record, &fields),
+            Err(FitError::TooManyFields)
+        );
+    }
+
+    #[test]
+    fn interleave_local_messages() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define(
+                FitGlobalMessageType::Record,
+                &[FitRecordFieldDefinition::HeartRate],
+            )
+            .unwrap();
+        let event = fit
+            .define(
+                FitGlobalMessageType::Event,
+                &[FitEventFieldDefinition::Event],
+            )
+            .unwrap();
+        let size = fit.size();
+
+        fit.push(record, &[110]).unwrap();
+        fit.push(event, &[0]).unwrap();
+        fit.push(record, &[115]).unwrap();
+
+        // File Id is local message type 0, Record 1 and Event 2.
+        let buf = fit.done().unwrap();
+        assert_eq!(buf[size..size + 6], [0x01, 110, 0x02, 0, 0x01, 115]);
+    }
+
+    #[test]
+    fn redefine_returns_same_local_message() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let fields = [FitRecordFieldDefinition::HeartRate];
+        let record = fit.define(FitGlobalMessageType::Record, &fields).unwrap();
+        let size = fit.size();
+
+        assert_eq!(
+            fit.define(FitGlobalMessageType::Record, &fields),
+            Ok(record)
+        );
+        assert_eq!(fit.size(), size);
+    }
+
+    const LAP_FIELDS: [FitLapFieldDefinition; 16] = [
+        FitLapFieldDefinition::Timestamp,
+        FitLapFieldDefinition::StartTime,
+        FitLapFieldDefinition::StartPositionLat,
+        FitLapFieldDefinition::StartPositionLong,
+        FitLapFieldDefinition::EndPositionLat,
+        FitLapFieldDefinition::EndPositionLong,
+        FitLapFieldDefinition::TotalElapsedTime,
+        FitLapFieldDefinition::TotalTimerTime,
+        FitLapFieldDefinition::TotalDistance,
+        FitLapFieldDefinition::TotalCalories,
+        FitLapFieldDefinition::AvgSpeed,
+        FitLapFieldDefinition::MaxSpeed,
+        FitLapFieldDefinition::AvgHeartRate,
+        FitLapFieldDefinition::MaxHeartRate,
+        FitLapFieldDefinition::Sport,
+        FitLapFieldDefinition::SubSport,
+    ];
+
+    #[test]
+    fn evict_least_recently_used_local_message() {
+        let mut fit = FitFile::<1024>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define(
+                FitGlobalMessageType::Record,
+                &[FitRecordFieldDefinition::HeartRate],
+            )
+            .unwrap();
+
+        // Fill local message types 2:15, then evict File Id (0).
+        for n in 0..15 {
+            fit.define(FitGlobalMessageType::Lap, &LAP_FIELDS[..=n])
+                .unwrap();
+        }
+        let size = fit.size();
+
+        // Record is still defined.
+        fit.push(record, &[110]).unwrap();
+        assert_eq!(fit.size(), size + 2);
+    }
+
+    #[test]
+    fn reemit_definition_of_evicted_local_message() {
+        let mut fit = FitFile::<1024>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define(
+                FitGlobalMessageType::Record,
+                &[FitRecordFieldDefinition::HeartRate],
+            )
+            .unwrap();
+
+        // Fill local message types 2:15, then evict File Id (0) and Record (1).
+        for n in 0..16 {
+            fit.define(FitGlobalMessageType::Lap, &LAP_FIELDS[..=n])
+                .unwrap();
+        }
+        let size = fit.size();
+        fit.push(record, &[110]).unwrap();
+
+        // Record is defined again in the least recently used local message type (2).
+        let buf = fit.done().unwrap();
+        assert_eq!(buf[size], 0x42);
+        assert_eq!(buf[size + 9..size + 11], [0x02, 110]);
+    }
+
+    #[test]
+    fn compress_timestamps() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define_compressed(
+                FitGlobalMessageType::Record,
+                &[
+                    FitRecordFieldDefinition::Timestamp,
+                    FitRecordFieldDefinition::HeartRate,
+                ],
+            )
+            .unwrap();
+        let size = fit.size();
+
+        let mut data = [0u8; 5];
+        for (ts, bpm) in [(1000u32, 110), (1001, 111), (1031, 112), (1063, 113)] {
+            data[0..4].copy_from_slice(&ts.to_le_bytes());
+            data[4] = bpm;
+            fit.push(record, &data).unwrap();
+        }
+
+        let buf = fit.done().unwrap();
+        // First timestamp is written in full.
+        assert_eq!(buf[size..size + 6], [0x01, 0xE8, 0x03, 0x00, 0x00, 110]);
+        // Definition without Timestamp in local message type 2, then offsets
+        // of 1001 and 1031 within the header.
+        assert_eq!(buf[size + 6], 0x42);
+        assert_eq!(buf[size + 15..size + 19], [0xC9, 111, 0xC7, 112]);
+        // 1063 is 32 seconds after 1031, so it is written in full.
+        assert_eq!(
+            buf[size + 19..size + 25],
+            [0x01, 0x27, 0x04, 0x00, 0x00, 113]
+        );
+    }
+
+    #[test]
+    fn compress_timestamps_requires_timestamp() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let result = fit.define_compressed(
+            FitGlobalMessageType::Record,
+            &[FitRecordFieldDefinition::HeartRate],
+        );
+        assert_eq!(result, Err(FitError::NoTimestampField));
+    }
+
+    #[test]
+    fn push_values() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define_compressed(
+                FitGlobalMessageType::Record,
+                &[
+                    FitRecordFieldDefinition::Timestamp,
+                    FitRecordFieldDefinition::PositionLat,
+                    FitRecordFieldDefinition::HeartRate,
+                ],
+            )
+            .unwrap();
+        let size = fit.size();
+
+        for ts in [1000u32, 1001] {
+            fit.push_values(
+                record,
+                &[
+                    (FitRecordFieldDefinition::Timestamp, ts.into()),
+                    (FitRecordFieldDefinition::PositionLat, (-2i32).into()),
+                    (FitRecordFieldDefinition::HeartRate, 110u8.into()),
+                ],
+            )
+            .unwrap();
+        }
+
+        let buf = fit.done().unwrap();
+        #[rustfmt::skip]
+        assert_eq!(
+            buf[size..size + 10],
+            [0x01, 0xE8, 0x03, 0x00, 0x00, 0xFE, 0xFF, 0xFF, 0xFF, 110]
+        );
+        // Definition without Timestamp, then the compressed message.
+        assert_eq!(
+            buf[size + 22..size + 28],
+            [0xC9, 0xFE, 0xFF, 0xFF, 0xFF, 110]
+        );
+    }
+
+    #[test]
+    fn push_values_must_match_definition() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define(
+                FitGlobalMessageType::Record,
+                &[
+                    FitRecordFieldDefinition::Speed,
+                    FitRecordFieldDefinition::HeartRate,
+                ],
+            )
+            .unwrap();
+        let size = fit.size();
+
+        // Wrong order.
+        let result = fit.push_values(
+            record,
+            &[
+                (FitRecordFieldDefinition::HeartRate, 110u8.into()),
+                (FitRecordFieldDefinition::Speed, 1000u16.into()),
+            ],
+        );
+        assert!(result.is_err());
+        // Wrong type.
+        let result = fit.push_values(
+            record,
+            &[
+                (FitRecordFieldDefinition::Speed, 1000u32.into()),
+                (FitRecordFieldDefinition::HeartRate, 110u8.into()),
+            ],
+        );
+        assert!(result.is_err());
+        // Missing field.
+        let result = fit.push_values(record, &[(FitRecordFieldDefinition::Speed, 1000u16.into())]);
+        assert!(result.is_err());
+        assert_eq!(fit.size(), size);
+    }
+
+    #[test]
+    fn push_must_match_definition_size() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define(
+                FitGlobalMessageType::Record,
+                &[
+                    FitRecordFieldDefinition::Speed,
+                    FitRecordFieldDefinition::HeartRate,
+                ],
+            )
+            .unwrap();
+        let size = fit.size();
+
+        assert_eq!(
+            fit.push(record, &[0, 0]),
+            Err(FitError::DataSizeMismatch {
+                expected: 3,
+                actual: 2
+            })
+        );
+        assert_eq!(
+            fit.push(record, &[0, 0, 0, 0]),
+            Err(FitError::DataSizeMismatch {
+                expected: 3,
+                actual: 4
+            })
+        );
+        assert_eq!(fit.size(), size);
+        assert_eq!(fit.push(record, &[0, 0, 0]), Ok(()));
+    }
+
+    #[test]
+    fn push_without_definition() {
+        let mut fit = FitFile::<512>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let mut other = FitFile::<512>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = other
+            .define(
+                FitGlobalMessageType::Record,
+                &[FitRecordFieldDefinition::HeartRate],
+            )
+            .unwrap();
+        assert_eq!(fit.push(record, &[110]), Err(FitError::NoActiveDefinition));
+    }
+
+    #[test]
+    fn push_values_of_compatible_types() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let lap = fit
+            .define(
+                FitGlobalMessageType::Lap,
+                &[
+                    FitLapFieldDefinition::Sport,
+                    FitLapFieldDefinition::MaxHeartRate,
+                ],
+            )
+            .unwrap();
+        let size = fit.size();
+
+        // Enums are unsigned bytes.
+        fit.push_values(
+            lap,
+            &[
+                (FitLapFieldDefinition::Sport, 1u8.into()),
+                (FitLapFieldDefinition::MaxHeartRate, FitValue::Enum(180)),
+            ],
+        )
+        .unwrap();
+        let buf = fit.done().unwrap();
+        assert_eq!(buf[size..size + 3], [0x01, 1, 180]);
+    }
+
+    #[test]
+    fn encode_decode_strings_and_arrays() {
+        // Arrays can't be larger than a field.
+        assert_eq!(
+            FitRecordFieldDefinition::PositionLat.array(64).err(),
+            Some(FitError::FieldTooLarge)
+        );
+        // Strings need a byte for their null terminator.
+        assert_eq!(
+            FitWorkoutFieldDefinition::WktName.string(0).err(),
+            Some(FitError::FieldTooSmall)
+        );
+        let options = FitFileOptions {
+            arch: FitMessageArchitecture::MSB,
+            ..Default::default()
+        };
+        let mut fit =
+            FitFile::<256>::with_options(FitFileType::Workout, FitDateTime::new(0), options)
+                .unwrap();
+        let workout = fit
+            .define(
+                FitGlobalMessageType::Workout,
+                &[
+                    FitWorkoutFieldDefinition::Sport.into(),
+                    FitWorkoutFieldDefinition::WktName.string(8).unwrap(),
+                    FitWorkoutFieldDefinition::NumValidSteps.array(2).unwrap(),
+                ],
+            )
+            .unwrap();
+        let size = fit.size();
+
+        let steps = FitArray::new(
+            FitBaseType::Uint16,
+            FitMessageArchitecture::LSB,
+            &[1, 0, 2, 0],
+        )
+        .unwrap();
+        fit.push_values(
+            workout,
+            &[
+                (FitWorkoutFieldDefinition::Sport, FitValue::Enum(1)),
+                (FitWorkoutFieldDefinition::WktName, "Tempo".into()),
+                (FitWorkoutFieldDefinition::NumValidSteps, steps.into()),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            fit.push_values(
+                workout,
+                &[
+                    (FitWorkoutFieldDefinition::Sport, FitValue::Enum(1)),
+                    (FitWorkoutFieldDefinition::WktName, "Intervals".into()),
+                    (FitWorkoutFieldDefinition::NumValidSteps, steps.into()),
+                ],
+            ),
+            Err(FitError::FieldMismatch { index: 1 })
+        );
+        let buf = fit.done().unwrap();
+
+        // Strings are null terminated and padded, arrays are in file order.
+        #[rustfmt::skip]
+        assert_eq!(
+            buf[size..size + 14],
+            [0x01, 1, b'T', b'e', b'm', b'p', b'o', 0, 0, 0, 0x00, 0x01, 0x00, 0x02]
+        );
+
+        let message = data_messages(buf, None).last().unwrap();
+        assert_eq!(
+            message.field(8).unwrap().value(),
+            Some(FitValue::String("Tempo"))
+        );
+        let Some(FitValue::Array(steps)) = message.field(6).unwrap().value() else {
+            panic!("expected an array");
+        };
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps.get(1), Some(FitValue::Uint16(2)));
+    }
+
+    #[test]
+    fn date_time_conversions() {
+        let utc = FitCalendarTime {
+            year: 2025,
+            month: 9,
+            day: 27,
+            hour: 16,
+            minute: 10,
+            second: 30,
+        };
+        let ts = FitDateTime::from_unix(1758989430).unwrap();
+        assert_eq!(ts.seconds(), 1758989430 - 631065600);
+        assert_eq!(ts.to_unix(), Some(1758989430));
+        assert_eq!(ts.to_utc(), Some(utc));
+
+        // Adding seconds saturates, checked additions fail past u32::MAX.
+        let last = FitDateTime::new(u32::MAX - 1);
+        assert_eq!((last + 10).seconds(), u32::MAX);
+        assert_eq!(last.checked_add(1), Some(FitDateTime::new(u32::MAX)));
+        assert_eq!(last.checked_add(2), None);
+        let last = FitLocalDateTime::new(u32::MAX);
+        assert_eq!((last + 1).seconds(), u32::MAX);
+        assert_eq!(last.checked_add(1), None);
+        assert_eq!(FitDateTime::from_iso8601("2025-09-27T16:10:30Z"), Some(ts));
+        assert_eq!(
+            FitDateTime::from_iso8601("2025-09-27T11:10:30.999-05:00"),
+            Some(ts)
+        );
+        assert_eq!(FitDateTime::from_iso8601("2025-09-27"), None);
+        assert_eq!(FitDateTime::from_iso8601("2025-02-29T16:10:30Z"), None);
+        assert_eq!(FitDateTime::from_utc(&utc), Some(ts));
+
+        let leap_day = FitCalendarTime {
+            month: 2,
+            day: 29,
+            ..utc
+        };
+        assert_eq!(FitDateTime::from_utc(&leap_day), None);
+        let leap_day = FitCalendarTime {
+            year: 2024,
+            ..leap_day
+        };
+        let ts = FitDateTime::from_utc(&leap_day).unwrap();
+        assert_eq!(ts.to_utc(), Some(leap_day));
+        assert_eq!(
+            (ts + 86400).to_utc().map(|t| (t.month, t.day)),
+            Some((3, 1))
+        );
+
+        // Relative to a system time, not a date.
+        let uptime = FitDateTime::new(3600);
+        assert!(uptime.is_system_time());
+        assert_eq!(uptime.to_unix(), None);
+        assert_eq!(uptime.to_local(3600), None);
+        assert_eq!(FitDateTime::from_unix(0), None);
+
+        let local = ts.to_local(10 * 3600).unwrap();
+        assert_eq!(local.utc_offset(ts), 10 * 3600);
+        assert_eq!(
+            local.to_calendar(),
+            FitCalendarTime {
+                month: 3,
+                day: 1,
+                hour: 2,
+                ..leap_day
+            }
+        );
+        assert_eq!(
+            FitLocalDateTime::from_calendar(&local.to_calendar()),
+            Some(local)
+        );
+    }
+
+    #[test]
+    fn semicircle_positions() {
+        assert_eq!(
+            FitSemicircles::from_degrees(90.0),
+            Some(FitSemicircles::new(1 << 30))
+        );
+        assert_eq!(
+            FitSemicircles::from_degrees(-180.0),
+            Some(FitSemicircles::new(i32::MIN))
+        );
+        assert_eq!(
+            FitSemicircles::from_degrees(180.0),
+            Some(FitSemicircles::new(i32::MIN))
+        );
+        assert_eq!(FitSemicircles::from_degrees(180.5), None);
+        assert_eq!(
+            FitSemicircles::from_microdegrees(-45_000_000),
+            Some(FitSemicircles::new(-(1 << 29)))
+        );
+        assert_eq!(
+            FitSemicircles::from_degrees(52.370216),
+            FitSemicircles::from_microdegrees(52_370_216)
+        );
+        assert_eq!(
+            FitSemicircles::from_degrees_f32(-90.0),
+            Some(FitSemicircles::new(-(1 << 30)))
+        );
+        assert_eq!(FitSemicircles::INVALID.to_degrees(), None);
+        assert_eq!(FitSemicircles::INVALID.to_microdegrees(), None);
+
+        // Angles rounding to the last semicircle before 180 degrees wrap
+        // to -180 rather than becoming invalid.
+        let semicircle = 180.0 / (1u64 << 31) as f64;
+        assert_eq!(
+            FitSemicircles::from_degrees(180.0 - semicircle),
+            Some(FitSemicircles::new(i32::MIN))
+        );
+        assert_eq!(
+            FitSemicircles::from_degrees(180.0 - semicircle * 2.0),
+            Some(FitSemicircles::new(i32::MAX - 1))
+        );
+        let mut degrees = 180.0f64;
+        for _ in 0..10_000 {
+            degrees = f64::from_bits(degrees.to_bits() - 1_000_000);
+            for degrees in [degrees, -degrees] {
+                let semicircles = FitSemicircles::from_degrees(degrees).unwrap();
+                assert!(semicircles.is_valid(), "{degrees}");
+            }
+        }
+        for microdegrees in (179_999_000..=180_000_000).flat_map(|m| [m, -m]) {
+            let semicircles = FitSemicircles::from_microdegrees(microdegrees).unwrap();
+            assert!(semicircles.is_valid(), "{microdegrees}");
+        }
+        let mut degrees = 180.0f32;
+        for _ in 0..100 {
+            degrees = f32::from_bits(degrees.to_bits() - 1);
+            assert!(FitSemicircles::from_degrees_f32(degrees)
+                .unwrap()
+                .is_valid());
+        }
+
+        let position = FitPosition::from_microdegrees(52_370_216, 4_895_168).unwrap();
+        assert_eq!(position.to_microdegrees(), Some((52_370_216, 4_895_168)));
+        assert_eq!(FitPosition::from_degrees(90.5, 0.0), None);
+
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define(
+                FitGlobalMessageType::Record,
+                &[
+                    FitRecordFieldDefinition::PositionLat,
+                    FitRecordFieldDefinition::PositionLong,
+                ],
+            )
+            .unwrap();
+        for (lat, long) in [
+            (position.lat, position.long),
+            (FitSemicircles::INVALID, FitSemicircles::INVALID),
+        ] {
+            fit.push_values(
+                record,
+                &[
+                    (FitRecordFieldDefinition::PositionLat, lat.into()),
+                    (FitRecordFieldDefinition::PositionLong, long.into()),
+                ],
+            )
+            .unwrap();
+        }
+        let buf = fit.done().unwrap();
+
+        let mut positions = data_messages(buf, Some(FitGlobalMessageType::Record)).map(|message| {
+            message.position(
+                FitRecordFieldDefinition::PositionLat,
+                FitRecordFieldDefinition::PositionLong,
+            )
+        });
+        assert_eq!(positions.next(), Some(Some(position)));
+        assert_eq!(positions.next(), Some(None));
+        assert_eq!(positions.next(), None);
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert_eq!(FitBaseType::Enum.invalid(), 0xFF);
+        assert_eq!(FitBaseType::Sint16.invalid(), 0x7FFF);
+        assert_eq!(FitBaseType::Uint32z.invalid(), 0);
+        assert!(FitBaseType::Uint16.is_endian());
+        assert!(!FitBaseType::Uint8.is_endian());
+
+        let options = FitFileOptions {
+            arch: FitMessageArchitecture::MSB,
+            ..Default::default()
+        };
+        let mut fit =
+            FitFile::<128>::with_options(FitFileType::Activity, FitDateTime::new(0), options)
+                .unwrap();
+        let record = fit
+            .define(
+                FitGlobalMessageType::Record,
+                &[
+                    FitRecordFieldDefinition::HeartRate,
+                    FitRecordFieldDefinition::Altitude,
+                    FitRecordFieldDefinition::Grade,
+                ],
+            )
+            .unwrap();
+        let serial = fit
+            .define(
+                FitGlobalMessageType::FileId,
+                &[FitFileIdFieldDefinition::SerialNumber],
+            )
+            .unwrap();
+        let size = fit.size();
+
+        let hr: Option<u8> = None;
+        fit.push_values(
+            record,
+            &[
+                (FitRecordFieldDefinition::HeartRate, hr.into()),
+                (FitRecordFieldDefinition::Altitude, Some(2600u16).into()),
+                (FitRecordFieldDefinition::Grade, None::<i16>.into()),
+            ],
+        )
+        .unwrap();
+        fit.push_values(
+            serial,
+            &[(FitFileIdFieldDefinition::SerialNumber, None::<u32>.into())],
+        )
+        .unwrap();
+        assert_eq!(
+            fit.push_values(
+                record,
+                &[
+                    (FitRecordFieldDefinition::HeartRate, hr.into()),
+                    (FitRecordFieldDefinition::Altitude, None::<u32>.into()),
+                    (FitRecordFieldDefinition::Grade, None::<i16>.into()),
+                ],
+            ),
+            Err(FitError::FieldMismatch { index: 1 })
+        );
+        let buf = fit.done().unwrap();
+
+        // uint8 0xFF, sint16 0x7FFF, and uint32z 0.
+        #[rustfmt::skip]
+        assert_eq!(
+            buf[size..size + 11],
+            [0x01, 0xFF, 0x0A, 0x28, 0x7F, 0xFF, 0x02, 0x00, 0x00, 0x00, 0x00]
+        );
+
+        let values: heapless::Vec<_, 4> = data_messages(buf, None)
+            .filter(|message| message.offset() >= size)
+            .flat_map(|message| message.fields())
+            .map(|field| field.value())
+            .collect();
+        assert_eq!(values, [None, Some(FitValue::Uint16(2600)), None, None]);
+    }
+
+    #[test]
+    fn physical_values() {
+        let altitude = FitRecordFieldDefinition::Altitude;
+        assert_eq!((altitude.scale(), altitude.offset()), (5.0, 500));
+        assert_eq!(altitude.units(), "m");
+        assert_eq!(altitude.stored(-12.0), Some(FitValue::Uint16(2440)));
+        assert_eq!(altitude.physical(&FitValue::Uint16(2440)), Some(-12.0));
+        assert_eq!(altitude.stored(-600.0), None);
+        assert_eq!(altitude.stored(20000.0), None);
+        assert_eq!(
+            FitLapFieldDefinition::TotalElapsedTime.stored(90.5004),
+            Some(FitValue::Uint32(90500))
+        );
+        assert_eq!(FitWorkoutFieldDefinition::WktName.stored(1.0), None);
+
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let record = fit
+            .define(
+                FitGlobalMessageType::Record,
+                &[FitRecordFieldDefinition::Speed],
+            )
+            .unwrap();
+        let speed = FitRecordFieldDefinition::Speed.stored(3.25).unwrap();
+        fit.push_values(record, &[(FitRecordFieldDefinition::Speed, speed)])
+            .unwrap();
+        let buf = fit.done().unwrap();
+
+        let message = data_messages(buf, None).last().unwrap();
+        let field = message.field(6).unwrap();
+        assert_eq!(field.value(), Some(FitValue::Uint16(3250)));
+        assert_eq!(field.physical::<FitRecordFieldDefinition>(), Some(3.25));
+        assert_eq!(field.physical_value(), Some(3.25));
+        assert_eq!(field.units(), Some("m/s"));
+        assert_eq!(field.scale(), Some(1000.0));
+    }
+
+    #[test]
+    fn profile_of_messages_not_encoded() {
+        let hrv = FitGlobalMessageType::Hrv;
+        assert_eq!(crate::field_name(&hrv, 0), Some("time"));
+        assert_eq!(crate::field_scale(&hrv, 0), Some(1000.0));
+        assert_eq!(crate::field_units(&hrv, 0), Some("s"));
+        assert_eq!(
+            crate::field_physical(&hrv, 0, &FitValue::Uint16(850)),
+            Some(0.85)
+        );
+
+        let length = FitGlobalMessageType::Length;
+        assert_eq!(crate::field_name(&length, 5), Some("total_strokes"));
+        assert_eq!(crate::field_scale(&length, 5), Some(1.0));
+        assert_eq!(crate::field_units(&length, 5), Some("strokes"));
+        assert_eq!(crate::field_name(&length, 6), Some("avg_speed"));
+        assert_eq!(crate::field_scale(&length, 6), Some(1000.0));
+        assert_eq!(crate::field_units(&length, 6), Some("m/s"));
+
+        let phase = FitSessionFieldDefinition::AvgLeftPowerPhase;
+        assert_eq!((phase.scale(), phase.units()), (0.7111111, "degrees"));
+    }
+
+    #[test]
+    fn record_activity() {
+        let ts = FitDateTime::new(1_000_000_000);
+        let fit = FitFile::<1024>::new(FitFileType::Activity, ts).unwrap();
+        let mut recorder =
+            ActivityRecorder::start(fit, FitSportType::Running, FitSubSportType::Generic, ts)
+                .unwrap();
+        recorder.set_utc_offset(3600);
+        for n in 1..=10 {
+            let timestamp = ts + n;
+            recorder
+                .record(&ActivitySample {
+                    timestamp,
+                    distance: Some(10.0 * n as f32),
+                    heart_rate: Some(100 + n as u8),
+                    ..Default::default()
+                })
+                .unwrap();
+            match n {
+                4 => recorder.pause(timestamp).unwrap(),
+                6 => recorder.lap(timestamp).unwrap(),
+                7 => recorder.resume(timestamp).unwrap(),
+                _ => {}
+            }
+        }
+        assert!(!recorder.is_paused());
+        let mut fit = recorder.finish(ts + 10).unwrap();
+        let buf = fit.done().unwrap();
+
+        let messages = || data_messages(buf, None);
+        let count = |global| {
+            messages()
+                .filter(|m| m.global_message() == Some(global))
+                .count()
+        };
+        assert_eq!(count(FitGlobalMessageType::Record), 10);
+        // Start, pause, resume, and stop when finishing.
+        assert_eq!(count(FitGlobalMessageType::Event), 4);
+
+        let value = |global, field: u8, n: usize| {
+            messages()
+                .filter(|m| m.global_message() == Some(global))
+                .nth(n)
+                .and_then(|m| m.field(field))
+                .and_then(|f| f.value())
+        };
+        let lap = |field: FitLapFieldDefinition, n| {
+            value(FitGlobalMessageType::Lap, field.field_number(), n)
+        };
+        assert_eq!(
+            lap(FitLapFieldDefinition::MessageIndex, 1),
+            Some(FitValue::Uint16(1))
+        );
+        // The first lap ran 4 of its 6 seconds.
+        assert_eq!(
+            lap(FitLapFieldDefinition::TotalElapsedTime, 0),
+            Some(FitValue::Uint32(6000))
+        );
+        assert_eq!(
+            lap(FitLapFieldDefinition::TotalTimerTime, 0),
+            Some(FitValue::Uint32(4000))
+        );
+        assert_eq!(
+            lap(FitLapFieldDefinition::TotalDistance, 1),
+            Some(FitValue::Uint32(4000))
+        );
+        // Samples while paused aren't counted.
+        assert_eq!(
+            lap(FitLapFieldDefinition::MaxHeartRate, 0),
+            Some(FitValue::Uint8(104))
+        );
+        assert_eq!(
+            lap(FitLapFieldDefinition::AvgHeartRate, 1),
+            Some(FitValue::Uint8(109))
+        );
+        assert_eq!(lap(FitLapFieldDefinition::TotalTimerTime, 2), None);
+
+        let session = |field: FitSessionFieldDefinition| {
+            value(FitGlobalMessageType::Session, field.field_number(), 0)
+        };
+        assert_eq!(
+            session(FitSessionFieldDefinition::NumLaps),
+            Some(FitValue::Uint16(2))
+        );
+        assert_eq!(
+            session(FitSessionFieldDefinition::TotalTimerTime),
+            Some(FitValue::Uint32(7000))
+        );
+        assert_eq!(
+            session(FitSessionFieldDefinition::AvgSpeed),
+            Some(FitValue::Uint16(14286))
+        );
+
+        let activity = |field: FitActivityFieldDefinition| {
+            value(FitGlobalMessageType::Activity, field.field_number(), 0)
+        };
+        assert_eq!(
+            activity(FitActivityFieldDefinition::NumSessions),
+            Some(FitValue::Uint16(1))
+        );
+        assert_eq!(
+            activity(FitActivityFieldDefinition::LocalTimestamp),
+            Some(FitValue::Uint32(1_000_003_610))
+        );
+    }
+
+    #[test]
+    fn build_workout() {
+        let mut workout = WorkoutBuilder::<8>::new("Intervals", FitSportType::Running);
+        workout
+            .warmup(
+                WorkoutDuration::Time(600.0),
+                WorkoutTarget::HeartRateZone(2),
+            )
+            .repeat(
+                5,
+                &[
+                    WorkoutStep::active(
+                        WorkoutDuration::Distance(400.0),
+                        WorkoutTarget::Speed {
+                            low: 4.0,
+                            high: 4.5,
+                        },
+                    )
+                    .name("Fast")
+                    .notes("Stay relaxed"),
+                    WorkoutStep::recovery(
+                        WorkoutDuration::Time(90.0),
+                        WorkoutTarget::HeartRate {
+                            low: WorkoutHeartRate::Bpm(100),
+                            high: WorkoutHeartRate::Percent(70),
+                        },
+                    ),
+                ],
+            )
+            .cooldown(WorkoutDuration::Open, WorkoutTarget::Open);
+        assert_eq!(workout.num_valid_steps(), 5);
+
+        let mut fit = FitFile::<512>::new(FitFileType::Workout, FitDateTime::new(0)).unwrap();
+        workout.write(&mut fit).unwrap();
+        let buf = fit.done().unwrap();
+
+        let steps: heapless::Vec<_, 8> =
+            data_messages(buf, Some(FitGlobalMessageType::WorkoutStep)).collect();
+        assert_eq!(steps.len(), 5);
+        let value = |step: usize, field: FitWorkoutStepFieldDefinition| {
+            steps[step]
+                .field(field.field_number())
+                .and_then(|f| f.value())
+        };
+        use FitWorkoutStepFieldDefinition as Step;
+
+        // Durations in ms and cm.
+        assert_eq!(
+            value(0, Step::DurationValue),
+            Some(FitValue::Uint32(600_000))
+        );
+        assert_eq!(value(0, Step::TargetValue), Some(FitValue::Uint32(2)));
+        assert_eq!(
+            value(1, Step::DurationValue),
+            Some(FitValue::Uint32(40_000))
+        );
+        assert_eq!(
+            value(1, Step::CustomTargetValueHigh),
+            Some(FitValue::Uint32(4500))
+        );
+        assert_eq!(value(1, Step::WktStepName), Some(FitValue::String("Fast")));
+        assert_eq!(
+            value(1, Step::Notes),
+            Some(FitValue::String("Stay relaxed"))
+        );
+        // Heart rates in bpm are offset by 100.
+        assert_eq!(
+            value(2, Step::CustomTargetValueLow),
+            Some(FitValue::Uint32(200))
+        );
+        assert_eq!(
+            value(2, Step::CustomTargetValueHigh),
+            Some(FitValue::Uint32(70))
+        );
+        assert_eq!(value(2, Step::WktStepName), None);
+
+        // Repeat steps 1 and 2 5 times.
+        assert_eq!(
+            value(3, Step::DurationType),
+            Some(FitValue::Enum(
+                FitWorkoutStepDuration::RepeatUntilStepsCmplt as u8
+            ))
+        );
+        assert_eq!(value(3, Step::DurationValue), Some(FitValue::Uint32(1)));
+        assert_eq!(value(3, Step::TargetValue), Some(FitValue::Uint32(5)));
+        assert_eq!(value(4, Step::DurationValue), None);
+
+        let mut workout = WorkoutBuilder::<2>::new("Long", FitSportType::Running);
+        workout.repeat(
+            3,
+            &[WorkoutStep::active(
+                WorkoutDuration::Time(60.0),
+                WorkoutTarget::Open,
+            )],
+        );
+        workout.cooldown(WorkoutDuration::Open, WorkoutTarget::Open);
+        let mut fit = FitFile::<512>::new(FitFileType::Workout, FitDateTime::new(0)).unwrap();
+        assert_eq!(workout.write(&mut fit), Err(WorkoutError::TooManySteps));
+
+        // Repeats need steps, done at least once.
+        let step = WorkoutStep::active(WorkoutDuration::Time(60.0), WorkoutTarget::Open);
+        for (times, steps) in [(3, &[] as &[WorkoutStep]), (0, &[step])] {
+            let mut workout = WorkoutBuilder::<8>::new("Empty", FitSportType::Running);
+            workout.repeat(times, steps);
+            assert_eq!(workout.num_valid_steps(), 0);
+            assert_eq!(workout.write(&mut fit), Err(WorkoutError::EmptyRepeat));
+        }
+    }
+
+    #[test]
+    fn build_course() {
+        let a = FitPosition::from_degrees(48.8566, 2.3522).unwrap();
+        let b = FitPosition::from_degrees(48.8606, 2.3376).unwrap();
+        let distance = a.distance(&b).unwrap();
+        assert!((distance - 1157.0).abs() < 0.1, "{distance}");
+        // Across 180 degrees the short way.
+        let a = FitPosition::from_degrees(60.0, 179.999).unwrap();
+        let b = FitPosition::from_degrees(60.0, -179.999).unwrap();
+        assert!((a.distance(&b).unwrap() - 111.2).abs() < 0.1);
+
+        // Segments of 2^16 semicircles along the equator, 610.81 m each.
+        let point = |long: i32, altitude: f32| CourseTrackPoint {
+            position: FitPosition {
+                lat: FitSemicircles::new(0),
+                long: FitSemicircles::new(long << 16),
+            },
+            altitude: Some(altitude),
+        };
+        let track = [point(0, 10.0), point(1, 25.0), point(2, 20.0)];
+        let points = [
+            CoursePoint::new(1, FitCoursePointType::Left).name("Turn left"),
+            CoursePoint::new(2, FitCoursePointType::Water),
+        ];
+        let course = CourseBuilder::new("Loop", FitSportType::Cycling, &track)
+            .points(&points)
+            .speed(5.0);
+
+        let ts = FitDateTime::new(1_000_000_000);
+        let mut fit = FitFile::<512>::new(FitFileType::Course, ts).unwrap();
+        course.write(&mut fit, ts).unwrap();
+        let buf = fit.done().unwrap();
+
+        let messages = || data_messages(buf, None);
+        let globals: heapless::Vec<_, 16> = messages().filter_map(|m| m.global_message()).collect();
+        use FitGlobalMessageType as Global;
+        assert_eq!(
+            globals,
+            [
+                Global::FileId,
+                Global::Course,
+                Global::Lap,
+                Global::Event,
+                Global::Record,
+                Global::Record,
+                Global::Record,
+                Global::Event,
+                Global::CoursePoint,
+                Global::CoursePoint,
+            ]
+        );
+
+        let value = |global, field: u8, n: usize| {
+            messages()
+                .filter(|m| m.global_message() == Some(global))
+                .nth(n)
+                .and_then(|m| m.field(field))
+                .and_then(|f| f.value())
+        };
+        let lap = |field: FitLapFieldDefinition| value(Global::Lap, field.field_number(), 0);
+        assert_eq!(
+            lap(FitLapFieldDefinition::TotalDistance),
+            Some(FitValue::Uint32(122_162))
+        );
+        assert_eq!(
+            lap(FitLapFieldDefinition::Timestamp),
+            Some(FitValue::Uint32(1_000_000_244))
+        );
+        assert_eq!(
+            lap(FitLapFieldDefinition::TotalAscent),
+            Some(FitValue::Uint16(15))
+        );
+        assert_eq!(
+            lap(FitLapFieldDefinition::TotalDescent),
+            Some(FitValue::Uint16(5))
+        );
+        assert_eq!(
+            value(
+                Global::Record,
+                FitRecordFieldDefinition::Distance.field_number(),
+                1
+            ),
+            Some(FitValue::Uint32(61_081))
+        );
+
+        let course_point = |field: FitCoursePointFieldDefinition, n| {
+            value(Global::CoursePoint, field.field_number(), n)
+        };
+        assert_eq!(
+            course_point(FitCoursePointFieldDefinition::Timestamp, 0),
+            Some(FitValue::Uint32(1_000_000_122))
+        );
+        assert_eq!(
+            course_point(FitCoursePointFieldDefinition::Name, 0),
+            Some(FitValue::String("Turn left"))
+        );
+        assert_eq!(
+            course_point(FitCoursePointFieldDefinition::Type, 1),
+            Some(FitValue::Enum(FitCoursePointType::Water as u8))
+        );
+        assert_eq!(course_point(FitCoursePointFieldDefinition::Name, 1), None);
+        assert_eq!(
+            course_point(FitCoursePointFieldDefinition::Distance, 1),
+            Some(FitValue::Uint32(122_162))
+        );
+
+        let points = [CoursePoint::new(3, FitCoursePointType::Summit)];
+        let course = CourseBuilder::new("Loop", FitSportType::Cycling, &track).points(&points);
+        let mut fit = FitFile::<512>::new(FitFileType::Course, ts).unwrap();
+        assert_eq!(
+            course.write(&mut fit, ts),
+            Err(CourseError::PointOutOfTrack)
+        );
+
+        let points = [
+            CoursePoint::new(2, FitCoursePointType::Summit),
+            CoursePoint::new(1, FitCoursePointType::Left),
+        ];
+        let course = CourseBuilder::new("Loop", FitSportType::Cycling, &track).points(&points);
+        assert_eq!(
+            course.write(&mut fit, ts),
+            Err(CourseError::PointsOutOfOrder)
+        );
+    }
+
+    #[cfg(feature = "gpx")]
+    #[test]
+    fn import_gpx() {
+        use crate::{GpxError, GpxTrack};
+
+        let gpx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1"
+     xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+  <trk>
+    <name>Morning ride</name>
+    <trkseg>
+      <trkpt lat="0" lon="0">
+        <ele>10.0</ele>
+        <time>2025-09-27T16:10:30Z</time>
+        <extensions>
+          <gpxtpx:TrackPointExtension>
+            <gpxtpx:atemp>21.4</gpxtpx:atemp>
+            <gpxtpx:hr>120</gpxtpx:hr>
+            <gpxtpx:cad>85</gpxtpx:cad>
+          </gpxtpx:TrackPointExtension>
+        </extensions>
+      </trkpt>
+    </trkseg>
+    <trkseg>
+      <trkpt lat="0" lon="0.01"><time>2025-09-27T18:11:30.500+02:00</time></trkpt>
+    </trkseg>
+  </trk>
+  <rte>
+    <rtept lat="46.2044" lon="6.1432"/>
+    <rtept lat="46.2101" lon="6.1517"/>
+  </rte>
+</gpx>"#;
+        let tracks = GpxTrack::parse(gpx).unwrap();
+        assert_eq!(tracks.len(), 2);
+        let track = &tracks[0];
+        assert_eq!(track.name.as_deref(), Some("Morning ride"));
+        assert_eq!(track.points.len(), 2);
+        let start = FitDateTime::from_unix(1_758_989_430).unwrap();
+        assert_eq!(track.points[0].time, Some(start));
+        assert_eq!(track.points[1].time, Some(start + 60));
+        assert_eq!(track.points[0].heart_rate, Some(120));
+        assert_eq!(track.points[0].cadence, Some(85));
+        assert_eq!(track.points[0].temperature, Some(21));
+        assert_eq!(track.points[1].heart_rate, None);
+
+        let buf = track
+            .to_activity(FitSportType::Cycling, FitSubSportType::Road)
+            .unwrap();
+        let records: heapless::Vec<_, 2> =
+            data_messages(&buf, Some(FitGlobalMessageType::Record)).collect();
+        let value = |n: usize, field: FitRecordFieldDefinition| {
+            records[n]
+                .field(field.field_number())
+                .and_then(|f| f.value())
+        };
+        use FitRecordFieldDefinition as Record;
+        assert_eq!(value(0, Record::HeartRate), Some(FitValue::Uint8(120)));
+        assert_eq!(value(0, Record::Cadence), Some(FitValue::Uint8(85)));
+        assert_eq!(value(0, Record::Temperature), Some(FitValue::Sint8(21)));
+        // 0.01 degrees along the equator in a minute.
+        assert_eq!(value(1, Record::Distance), Some(FitValue::Uint32(111_195)));
+        assert_eq!(value(1, Record::Speed), Some(FitValue::Uint16(18_533)));
+
+        let route = &tracks[1];
+        assert_eq!(
+            route.to_activity(FitSportType::Running, FitSubSportType::Generic),
+            Err(GpxError::MissingTime)
+        );
+        let buf = route.to_course(FitSportType::Running, start, 3.0).unwrap();
+        assert_eq!(
+            data_messages(&buf, Some(FitGlobalMessageType::Record)).count(),
+            2
+        );
+
+        assert_eq!(GpxTrack::parse("<kml/>"), Err(GpxError::NotGpx));
+        assert_eq!(
+            GpxTrack::parse(r#"<gpx><trk><trkpt lat="91" lon="0"/></trk></gpx>"#),
+            Err(GpxError::InvalidPosition)
+        );
+
+        // Readings that aren't finite numbers are ignored.
+        let tracks = GpxTrack::parse(
+            r#"<gpx><trk><trkpt lat="0" lon="0"><ele>NaN</ele><extensions>
+                <hr>NaN</hr><cad>inf</cad><atemp>-inf</atemp>
+            </extensions></trkpt></trk></gpx>"#,
+        )
+        .unwrap();
+        let point = tracks[0].points[0];
+        assert_eq!(point.altitude, None);
+        assert_eq!(point.heart_rate, None);
+        assert_eq!(point.cadence, None);
+        assert_eq!(point.temperature, None);
+
+        let empty = GpxTrack {
+            name: None,
+            points: std::vec::Vec::new(),
+        };
+        assert_eq!(
+            empty.to_course(FitSportType::Running, start, 3.0),
+            Err(GpxError::NoPoints)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn export_track() {
+        use crate::ExportTrack;
+
+        let ts = FitDateTime::from_unix(1_758_989_430).unwrap();
+        let fit = FitFile::<2048>::new(FitFileType::Activity, ts).unwrap();
+        let mut recorder =
+            ActivityRecorder::start(fit, FitSportType::Cycling, FitSubSportType::Road, ts).unwrap();
+        for n in 0..4u32 {
+            recorder
+                .record(&ActivitySample {
+                    timestamp: ts + n,
+                    position: FitPosition::from_degrees(46.2044, 6.1432 + n as f64 / 1000.0),
+                    altitude: Some(375.0),
+                    distance: Some(77.0 * n as f32),
+                    heart_rate: Some(120 + n as u8),
+                    power: Some(200),
+                    ..Default::default()
+                })
+                .unwrap();
+            if n == 1 {
+                recorder.lap(ts + n).unwrap();
+            }
+        }
+        let mut fit = recorder.finish(ts + 3).unwrap();
+        let track = ExportTrack::from_fit(fit.done().unwrap()).unwrap();
+        assert_eq!(track.sport, Some(FitSportType::Cycling));
+        assert_eq!(track.points.len(), 4);
+        assert_eq!(track.laps.len(), 2);
+
+        let mut gpx = std::vec::Vec::new();
+        track.write_gpx(&mut gpx).unwrap();
+        let gpx = std::string::String::from_utf8(gpx).unwrap();
+        assert!(gpx.contains(r#"<trkpt lat="46.2044000" lon="6.1452000">"#));
+        assert!(gpx.contains("<time>2025-09-27T16:10:32Z</time>"));
+        assert!(gpx.contains("<ele>375.0</ele>"));
+        assert!(gpx.contains("<gpxtpx:hr>123</gpxtpx:hr>"));
+        assert_eq!(gpx.matches("<trkpt ").count(), 4);
+
+        let mut tcx = std::vec::Vec::new();
+        track.write_tcx(&mut tcx).unwrap();
+        let tcx = std::string::String::from_utf8(tcx).unwrap();
+        assert!(tcx.contains(r#"<Activity Sport="Biking">"#));
+        assert!(tcx.contains("<Id>2025-09-27T16:10:30Z</Id>"));
+        assert!(tcx.contains(r#"<Lap StartTime="2025-09-27T16:10:31Z">"#));
+        assert!(tcx.contains("<DistanceMeters>154.00</DistanceMeters>"));
+        assert!(tcx.contains("<HeartRateBpm><Value>121</Value></HeartRateBpm>"));
+        assert!(tcx.contains("<ns3:Watts>200</ns3:Watts>"));
+        // Points up to the end of the first lap are in it.
+        let (first, second) = tcx.split_once("</Lap>").unwrap();
+        assert_eq!(first.matches("<Trackpoint>").count(), 2);
+        assert_eq!(second.matches("<Trackpoint>").count(), 2);
+
+        // Without laps, the lap spans the points, and without points the
+        // required elements still have values.
+        let points = track.points[1..].to_vec();
+        let track = ExportTrack {
+            points,
+            ..Default::default()
+        };
+        let mut tcx = std::vec::Vec::new();
+        track.write_tcx(&mut tcx).unwrap();
+        let tcx = std::string::String::from_utf8(tcx).unwrap();
+        assert!(tcx.contains("<Id>2025-09-27T16:10:31Z</Id>"));
+        assert!(tcx.contains(r#"<Lap StartTime="2025-09-27T16:10:31Z">"#));
+        assert!(tcx.contains("<DistanceMeters>154.00</DistanceMeters>"));
+
+        let mut tcx = std::vec::Vec::new();
+        ExportTrack::default().write_tcx(&mut tcx).unwrap();
+        let tcx = std::string::String::from_utf8(tcx).unwrap();
+        assert!(tcx.contains("<Id>1989-12-31T00:00:00Z</Id>"));
+        assert!(tcx.contains("<DistanceMeters>0.00</DistanceMeters>"));
+
+        // Points with a system time have no UTC time to write, and are
+        // left out.
+        let point = |timestamp| ActivitySample {
+            timestamp,
+            position: FitPosition::from_degrees(46.2044, 6.1432),
+            ..Default::default()
+        };
+        let track = ExportTrack {
+            points: std::vec![point(FitDateTime::new(1000)), point(ts)],
+            ..Default::default()
+        };
+        let mut gpx = std::vec::Vec::new();
+        track.write_gpx(&mut gpx).unwrap();
+        let gpx = std::string::String::from_utf8(gpx).unwrap();
+        assert_eq!(gpx.matches("<trkpt ").count(), 1);
+        assert!(gpx.contains("<metadata><time>2025-09-27T16:10:30Z</time></metadata>"));
+        let mut tcx = std::vec::Vec::new();
+        track.write_tcx(&mut tcx).unwrap();
+        let tcx = std::string::String::from_utf8(tcx).unwrap();
+        assert_eq!(tcx.matches("<Trackpoint>").count(), 1);
+        assert_eq!(tcx.matches("<Time>").count(), 1);
+        assert!(tcx.contains("<Id>2025-09-27T16:10:30Z</Id>"));
+    }
+
+    #[test]
+    fn decode_encoded_file() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(1000)).unwrap();
+        let record = fit
+            .define_compressed(
+                FitGlobalMessageType::Record,
+                &[
+                    FitRecordFieldDefinition::Timestamp,
+                    FitRecordFieldDefinition::HeartRate,
+                ],
+            )
+            .unwrap();
+        for (ts, bpm) in [(1000u32, 110), (1001, 111), (1040, 112)] {
+            let mut data = [0u8; 5];
+            data[0..4].copy_from_slice(&ts.to_le_bytes());
+            data[4] = bpm;
+            fit.push(record, &data).unwrap();
+        }
+        let buf = fit.done().unwrap();
+
+        let mut decoder = FitDecoder::new(buf).unwrap();
+        assert_eq!(decoder.header().size, 14);
+        assert_eq!(decoder.header().profile_version, 21171);
+        assert_eq!(decoder.header().data_size as usize, buf.len() - 16);
+
+        let Some(Ok(FitRecord::Definition(file_id))) = decoder.next() else {
+            panic!("expected File Id definition");
+        };
+        assert_eq!(file_id.global_message(), Some(FitGlobalMessageType::FileId));
+        let names: [Option<&str>; 3] = core::array::from_fn(|n| file_id.fields().nth(n)?.name());
+        assert_eq!(
+            names,
+            [Some("type"), Some("manufacturer"), Some("time_created")]
+        );
+
+        let Some(Ok(FitRecord::Data(file_id))) = decoder.next() else {
+            panic!("expected File Id message");
+        };
+        assert_eq!(file_id.field(0).unwrap().value(), Some(FitValue::Enum(4)));
+        assert_eq!(
+            file_id.field(4).unwrap().value(),
+            Some(FitValue::Uint32(1000))
+        );
+
+        let mut records = decoder.filter_map(|r| match r.unwrap() {
+            FitRecord::Data(msg) => Some(msg),
+            FitRecord::Definition(_) => None,
+        });
+        for (ts, bpm) in [(1000u32, 110u8), (1001, 111), (1040, 112)] {
+            let msg = records.next().unwrap();
+            assert_eq!(msg.global_message(), Some(FitGlobalMessageType::Record));
+            assert_eq!(msg.timestamp(), Some(FitDateTime::new(ts)));
+            let heart_rate = msg.field(3).unwrap();
+            assert_eq!(heart_rate.name(), Some("heart_rate"));
+            assert_eq!(heart_rate.value(), Some(FitValue::Uint8(bpm)));
+        }
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn decode_msb_definition() {
+        #[rustfmt::skip]
+        let mut buf = [
+            // Header
+            12, 0x20, 0xB3, 0x52, 13, 0, 0, 0, b'.', b'F', b'I', b'T',
+            // Record definition, MSB, Speed
+            0x40, 0, 1, 0, 20, 1, 6, 2, 132,
+            // Record message, Speed 0x0102
+            0x00, 0x01, 0x02,
+            // Header of an undefined local message type
+            0x01,
+            // CRC
+            0, 0,
+        ];
+        let crc = buf[..25]
+            .iter()
+            .fold(0, |crc, b| crate::fit::crc_get16(crc, *b));
+        buf[25..27].copy_from_slice(&crc.to_le_bytes());
+
+        let mut decoder = FitDecoder::new(&buf).unwrap();
+        assert_eq!(decoder.header().crc, None);
+
+        let Some(Ok(FitRecord::Definition(record))) = decoder.next() else {
+            panic!("expected Record definition");
+        };
+        assert_eq!(record.arch(), FitMessageArchitecture::MSB);
+        assert_eq!(record.global_msg_num(), 20);
+
+        let Some(Ok(FitRecord::Data(record))) = decoder.next() else {
+            panic!("expected Record message");
+        };
+        assert_eq!(
+            record.field(6).unwrap().value(),
+            Some(FitValue::Uint16(0x0102))
+        );
+
+        assert_eq!(
+            decoder.next(),
+            Some(Err(FitDecodeError::UndefinedLocalType(1)))
+        );
+        assert_eq!(decoder.next(), None);
+    }
+
+    #[test]
+    fn encode_decode_msb() {
+        let options = FitFileOptions {
+            arch: FitMessageArchitecture::MSB,
+            ..Default::default()
+        };
+        let mut fit =
+            FitFile::<128>::with_options(FitFileType::Activity, FitDateTime::new(1000), options)
+                .unwrap();
+        let record = fit
+            .define_compressed(
+                FitGlobalMessageType::Record,
+                &[
+                    FitRecordFieldDefinition::Timestamp,
+                    FitRecordFieldDefinition::Speed,
+                ],
+            )
+            .unwrap();
+        for (ts, speed) in [(1000u32, 1200u16), (1001, 1300)] {
+            let mut data = [0u8; 6];
+            data[0..4].copy_from_slice(&ts.to_be_bytes());
+            data[4..6].copy_from_slice(&speed.to_be_bytes());
+            fit.push(record, &data).unwrap();
+        }
+        let buf = fit.done().unwrap();
+
+        // File Id definition: MSB, global message number 0, then
+        // manufacturer (255) and time created (1000) big endian.
+        assert_eq!(buf[15..19], [0, 1, 0, 0]);
+        assert_eq!(buf[31..37], [0, 255, 0, 0, 0x03, 0xE8]);
+
+        let mut decoder = FitDecoder::new(buf).unwrap();
+        let mut records = decoder.by_ref().filter_map(|r| match r.unwrap() {
+            FitRecord::Data(msg) if msg.global_msg_num() == 20 => Some(msg),
+            _ => None,
+        });
+        for (ts, speed) in [(1000u32, 1200u16), (1001, 1300)] {
+            let msg = records.next().unwrap();
+            assert_eq!(msg.definition().arch(), FitMessageArchitecture::MSB);
+            assert_eq!(msg.timestamp(), Some(FitDateTime::new(ts)));
+            assert_eq!(msg.field(6).unwrap().value(), Some(FitValue::Uint16(speed)));
+        }
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn encode_decode_developer_fields() {
+        let mut fit = FitFile::<512>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let developer = fit.add_developer([0xAB; 16], 1).unwrap();
+        let smo2 = fit
+            .describe_field(developer, 0, FitBaseType::Uint8, "muscle_oxygen", "%")
+            .unwrap();
+        let record = fit
+            .define_with_developer_fields(
+                FitGlobalMessageType::Record,
+                &[FitRecordFieldDefinition::HeartRate],
+                &[smo2],
+            )
+            .unwrap();
+        let size = fit.size();
+        fit.push(record, &[110, 55]).unwrap();
+        let buf = fit.done().unwrap();
+
+        // Record definition (local message type 3) is flagged with developer
+        // fields, appended after the native fields.
+        assert_eq!(buf[size - 13], 0x63);
+        assert_eq!(buf[size - 4..size], [1, 0, 1, 0]);
+
+        let mut decoder = FitDecoder::new(buf).unwrap();
+        let mut messages = decoder.by_ref().filter_map(|r| match r.unwrap() {
+            FitRecord::Data(msg) => Some(msg),
+            FitRecord::Definition(_) => None,
+        });
+
+        let developer_data_id = messages.nth(1).unwrap();
+        assert_eq!(
+            developer_data_id.global_message(),
+            Some(FitGlobalMessageType::DeveloperDataId)
+        );
+        assert_eq!(
+            developer_data_id.field(1).unwrap().value(),
+            Some(FitValue::Byte(&[0xAB; 16]))
+        );
+
+        let field_description = messages.next().unwrap();
+        let names: [Option<FitValue>; 3] = core::array::from_fn(|n| {
+            field_description
+                .field([2, 3, 8][n])
+                .and_then(|f| f.value())
+        });
+        assert_eq!(
+            names,
+            [
+                Some(FitValue::Uint8(FitBaseType::Uint8 as u8)),
+                Some(FitValue::String("muscle_oxygen")),
+                Some(FitValue::String("%")),
+            ]
+        );
+
+        let record = messages.next().unwrap();
+        assert_eq!(record.field(3).unwrap().value(), Some(FitValue::Uint8(110)));
+        let smo2 = record.developer_fields().next().unwrap();
+        assert_eq!(smo2.number(), 0);
+        assert_eq!(smo2.developer_data_index(), 0);
+        assert_eq!(smo2.data(), &[55]);
+    }
+
+    #[test]
+    fn describe_field_of_unknown_developer() {
+        let mut fit = FitFile::<512>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let developer = fit.add_developer([0; 16], 1).unwrap();
+        let mut other = FitFile::<512>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        assert_eq!(
+            other.describe_field(developer, 0, FitBaseType::Uint8, "smo2", "%"),
+            Err(FitError::UnknownDeveloper)
+        );
+    }
+
+    #[test]
+    fn too_many_developers() {
+        let mut fit = FitFile::<8192>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        for index in 0..255 {
+            fit.add_developer([index; 16], 1).unwrap();
+        }
+        assert_eq!(
+            fit.add_developer([0; 16], 1),
+            Err(FitError::TooManyDevelopers)
+        );
+        let buf = fit.done().unwrap();
+        let indexes: heapless::Vec<_, 256> =
+            data_messages(buf, Some(FitGlobalMessageType::DeveloperDataId))
+                .map(|message| message.field(3).unwrap().value())
+                .collect();
+        assert_eq!(indexes.len(), 255);
+        assert_eq!(indexes[254], Some(FitValue::Uint8(254)));
+    }
+
+    #[test]
+    fn developer_field_arrays() {
+        let mut fit = FitFile::<512>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let developer = fit.add_developer([0; 16], 1).unwrap();
+        let smo2 = fit
+            .describe_field(developer, 0, FitBaseType::Uint8, "smo2", "%")
+            .unwrap();
+        assert_eq!(smo2.array(255).map(|f| f.get()), Ok([0, 255, 0]));
+        let heart_rates = fit
+            .describe_field(developer, 1, FitBaseType::Uint16, "heart_rates", "bpm")
+            .unwrap();
+        assert_eq!(heart_rates.array(128), Err(FitError::FieldTooLarge));
+        let note = fit
+            .describe_field(developer, 2, FitBaseType::String, "note", "")
+            .unwrap();
+        assert_eq!(note.string(0), Err(FitError::FieldTooSmall));
+        assert_eq!(note.string(16).map(|f| f.get()), Ok([2, 16, 0]));
+    }
+
+    #[test]
+    fn decode_crc_mismatch() {
+        let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+        let mut buf = [0u8; 39];
+        buf.copy_from_slice(fit.done().unwrap());
+        buf[20] ^= 0xFF;
+
+        let decoder = FitDecoder::new(&buf).unwrap();
+        assert_eq!(decoder.last(), Some(Err(FitDecodeError::CrcMismatch)));
+    }
+
+    #[test]
+    fn decode_invalid_header() {
+        assert_eq!(
+            FitDecoder::new(&[14, 0x20, 0, 0, 0, 0, 0, 0, b'.', b'F', b'I', b'X', 0, 0]).err(),
+            Some(FitDecodeError::InvalidHeader)
+        );
+        assert_eq!(
+            FitDecoder::new(&[14, 0x20, 0, 0, 9, 0, 0, 0, b'.', b'F', b'I', b'T', 0, 0]).err(),
+            Some(FitDecodeError::UnexpectedEnd)
+        );
+        // Largest data size, past the end of memory on 32-bit targets.
+        assert_eq!(
+            FitDecoder::new(&[
+                14, 0x20, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, b'.', b'F', b'I', b'T', 0, 0
+            ])
+            .err(),
+            Some(FitDecodeError::UnexpectedEnd)
+        );
+    }
 }
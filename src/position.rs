@@ -0,0 +1,197 @@
+use crate::{types::FitBaseType, value::FitValue};
+
+/// Semicircles in 180 degrees, 2^31.
+const SEMICIRCLES: i64 = 1 << 31;
+const MICRODEGREES: i64 = 180_000_000;
+/// Mean radius of the Earth in m.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Latitude or longitude in semicircles, as stored in position fields:
+/// 2^31 semicircles are 180 degrees.
+///
+/// Conversions from microdegrees only use integers, for targets without a
+/// floating point unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FitSemicircles(i32);
+
+/// Position of a latitude and longitude.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FitPosition {
+    pub lat: FitSemicircles,
+    pub long: FitSemicircles,
+}
+
+impl FitSemicircles {
+    /// Invalid value of `sint32` fields, for unknown positions.
+    pub const INVALID: Self = Self(i32::MAX);
+
+    pub const fn new(semicircles: i32) -> Self {
+        Self(semicircles)
+    }
+
+    /// Semicircles of an angle in degrees, from -180 to 180, rounded to the
+    /// nearest semicircle. 180 degrees wraps to -180, as do angles rounding
+    /// to the last semicircle before it, which is the invalid value.
+    pub fn from_degrees(degrees: f64) -> Option<Self> {
+        if !(-180.0..=180.0).contains(&degrees) {
+            return None;
+        }
+        let semicircles = degrees * (SEMICIRCLES as f64 / 180.0);
+        let rounded = if semicircles < 0.0 {
+            semicircles - 0.5
+        } else {
+            semicircles + 0.5
+        };
+        match rounded as i64 {
+            semicircles if semicircles >= Self::INVALID.0 as i64 => Some(Self(i32::MIN)),
+            semicircles => Some(Self(semicircles as i32)),
+        }
+    }
+
+    /// Semicircles of an angle in degrees, see
+    /// [`FitSemicircles::from_degrees`].
+    pub fn from_degrees_f32(degrees: f32) -> Option<Self> {
+        Self::from_degrees(degrees as f64)
+    }
+
+    /// Semicircles of an angle in millionths of a degree, from -180_000_000
+    /// to 180_000_000, rounded to the nearest semicircle.
+    pub fn from_microdegrees(microdegrees: i32) -> Option<Self> {
+        let microdegrees = microdegrees as i64;
+        if !(-MICRODEGREES..=MICRODEGREES).contains(&microdegrees) {
+            return None;
+        }
+        let semicircles = div_round(microdegrees * SEMICIRCLES, MICRODEGREES);
+        Some(Self(semicircles as i32))
+    }
+
+    pub const fn semicircles(&self) -> i32 {
+        self.0
+    }
+
+    pub const fn is_valid(&self) -> bool {
+        self.0 != Self::INVALID.0
+    }
+
+    /// Angle in degrees, `None` if invalid.
+    pub fn to_degrees(&self) -> Option<f64> {
+        self.is_valid()
+            .then(|| self.0 as f64 * (180.0 / SEMICIRCLES as f64))
+    }
+
+    /// Angle in degrees, see [`FitSemicircles::to_degrees`].
+    pub fn to_degrees_f32(&self) -> Option<f32> {
+        self.to_degrees().map(|degrees| degrees as f32)
+    }
+
+    /// Angle in millionths of a degree, rounded, `None` if invalid.
+    pub fn to_microdegrees(&self) -> Option<i32> {
+        self.is_valid()
+            .then(|| div_round(self.0 as i64 * MICRODEGREES, SEMICIRCLES) as i32)
+    }
+}
+
+impl FitPosition {
+    /// Position of a latitude from -90 to 90 and a longitude from -180 to
+    /// 180 degrees.
+    pub fn from_degrees(lat: f64, long: f64) -> Option<Self> {
+        if !(-90.0..=90.0).contains(&lat) {
+            return None;
+        }
+        Some(Self {
+            lat: FitSemicircles::from_degrees(lat)?,
+            long: FitSemicircles::from_degrees(long)?,
+        })
+    }
+
+    /// Position of a latitude and longitude in millionths of a degree.
+    pub fn from_microdegrees(lat: i32, long: i32) -> Option<Self> {
+        if !(-90_000_000..=90_000_000).contains(&lat) {
+            return None;
+        }
+        Some(Self {
+            lat: FitSemicircles::from_microdegrees(lat)?,
+            long: FitSemicircles::from_microdegrees(long)?,
+        })
+    }
+
+    /// Latitude and longitude in degrees, `None` if invalid.
+    pub fn to_degrees(&self) -> Option<(f64, f64)> {
+        Some((self.lat.to_degrees()?, self.long.to_degrees()?))
+    }
+
+    /// Latitude and longitude in millionths of a degree, `None` if invalid.
+    pub fn to_microdegrees(&self) -> Option<(i32, i32)> {
+        Some((self.lat.to_microdegrees()?, self.long.to_microdegrees()?))
+    }
+
+    /// Distance to `other` in m, `None` if either position is invalid.
+    ///
+    /// Uses an equirectangular projection at the mean latitude, which is
+    /// accurate for the short segments between points of a track.
+    pub fn distance(&self, other: &FitPosition) -> Option<f64> {
+        const RADIANS: f64 = core::f64::consts::PI / SEMICIRCLES as f64;
+        if !(self.lat.is_valid() && self.long.is_valid()) {
+            return None;
+        }
+        if !(other.lat.is_valid() && other.long.is_valid()) {
+            return None;
+        }
+        let lat = self.lat.0 as i64;
+        let other_lat = other.lat.0 as i64;
+        // Wrapping difference takes the short way across 180 degrees.
+        let long = other.long.0.wrapping_sub(self.long.0);
+
+        let x = long as f64 * RADIANS * cos((lat + other_lat) as f64 / 2.0 * RADIANS);
+        let y = (other_lat - lat) as f64 * RADIANS;
+        Some(EARTH_RADIUS * sqrt(x * x + y * y))
+    }
+}
+
+impl From<FitSemicircles> for FitValue<'_> {
+    fn from(value: FitSemicircles) -> Self {
+        Self::Sint32(value.0)
+    }
+}
+
+impl From<Option<FitSemicircles>> for FitValue<'_> {
+    fn from(value: Option<FitSemicircles>) -> Self {
+        value.map_or(Self::Invalid(FitBaseType::Sint32), Self::from)
+    }
+}
+
+/// Division rounded to the nearest integer, halves away from zero.
+fn div_round(numerator: i64, denominator: i64) -> i64 {
+    let half = denominator / 2;
+    if numerator < 0 {
+        (numerator - half) / denominator
+    } else {
+        (numerator + half) / denominator
+    }
+}
+
+/// Cosine of an angle from -pi/2 to pi/2 radians, by its Taylor series,
+/// as `core` has no trigonometry.
+fn cos(x: f64) -> f64 {
+    let x2 = x * x;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for n in (2..=16).step_by(2) {
+        term *= -x2 / (n * (n - 1)) as f64;
+        sum += term;
+    }
+    sum
+}
+
+/// Square root by Newton's method, as `core` has no `sqrt`.
+fn sqrt(x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    // Halving the exponent is a close first guess.
+    let mut root = f64::from_bits((x.to_bits() >> 1) + (1023 << 51));
+    for _ in 0..6 {
+        root = (root + x / root) / 2.0;
+    }
+    root
+}
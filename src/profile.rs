@@ -1,345 +1,206 @@
-use crate::types::FitBaseType;
+use crate::{
+    fit::FitError,
+    types::{FitBaseType, FitGlobalMessageType},
+    value::FitValue,
+};
 
 pub trait FitFieldDefinition {
     fn base_type(&self) -> FitBaseType;
     fn field_number(&self) -> u8;
+    /// Field name as written in the FIT Profile.
+    fn name(&self) -> &'static str;
+    /// Field for a field number. Subfields sharing a number map to the main field.
+    fn from_field_number(field_number: u8) -> Option<Self>
+    where
+        Self: Sized;
 
-    fn get(&self) -> [u8; 3] {
-        [
-            self.field_number(),
-            self.base_type().size() as u8,
-            self.base_type() as u8,
-        ]
-    }
-}
-
-///////////////////////////
-/////  FILE SETTINGS  /////
-///////////////////////////
-
-pub enum FitFileIdFieldDefinition {
-    Type,         // Required
-    Manufacturer, // Required
-    Product,
-    SerialNumber,
-    TimeCreated, // Required
-    Number,
-    ProductName,
-}
-
-impl FitFieldDefinition for FitFileIdFieldDefinition {
-    fn base_type(&self) -> FitBaseType {
-        match self {
-            Self::Type => FitBaseType::Enum,
-            Self::Manufacturer => FitBaseType::Uint16,
-            Self::Product => FitBaseType::Uint16,
-            Self::SerialNumber => FitBaseType::Uint32z,
-            Self::TimeCreated => FitBaseType::Uint32,
-            Self::Number => FitBaseType::Uint16,
-            Self::ProductName => FitBaseType::String,
-        }
-    }
-
-    fn field_number(&self) -> u8 {
-        match self {
-            Self::Type => 0,
-            Self::Manufacturer => 1,
-            Self::Product => 2,
-            Self::SerialNumber => 3,
-            Self::TimeCreated => 4,
-            Self::Number => 5,
-            Self::ProductName => 8,
-        }
-    }
-}
-
-////////////////////////////
-// ACTIVITY FILE SETTINGS //
-////////////////////////////
-
-pub enum FitActivityFieldDefinition {
-    Timestamp,
-    TotalTimerTime,
-    NumSessions,
-    Type,
-    Event,
-    EventType,
-    LocalTimestamp,
-    EventGroup,
-}
-
-impl FitFieldDefinition for FitActivityFieldDefinition {
-    fn base_type(&self) -> FitBaseType {
-        match self {
-            Self::Timestamp => FitBaseType::Uint32,
-            Self::TotalTimerTime => FitBaseType::Uint32,
-            Self::NumSessions => FitBaseType::Uint16,
-            Self::Type => FitBaseType::Enum,
-            Self::Event => FitBaseType::Enum,
-            Self::EventType => FitBaseType::Enum,
-            Self::LocalTimestamp => FitBaseType::Uint32,
-            Self::EventGroup => FitBaseType::Uint8,
-        }
-    }
-
-    fn field_number(&self) -> u8 {
-        match self {
-            Self::Timestamp => 253,
-            Self::TotalTimerTime => 0,
-            Self::NumSessions => 1,
-            Self::Type => 2,
-            Self::Event => 3,
-            Self::EventType => 4,
-            Self::LocalTimestamp => 5,
-            Self::EventGroup => 6,
-        }
-    }
-}
-
-pub enum FitSessionFieldDefinition {
-    Timestamp,
-    StartTime,
-    TotalElapsedTime,
-    TotalTimerTime,
-    TotalDistance,
-    MessageIndex,
-}
-
-impl FitFieldDefinition for FitSessionFieldDefinition {
-    fn base_type(&self) -> FitBaseType {
-        match self {
-            Self::Timestamp => FitBaseType::Uint32,
-            Self::StartTime => FitBaseType::Uint32,
-            Self::TotalElapsedTime => FitBaseType::Uint32,
-            Self::TotalTimerTime => FitBaseType::Uint32,
-            Self::TotalDistance => FitBaseType::Uint32,
-            Self::MessageIndex => FitBaseType::Uint16,
-        }
-    }
-
-    fn field_number(&self) -> u8 {
-        match self {
-            Self::StartTime => 2,
-            Self::TotalElapsedTime => 7,
-            Self::TotalTimerTime => 8,
-            Self::TotalDistance => 9,
-            Self::Timestamp => 253,
-            Self::MessageIndex => 254,
-        }
+    /// Size of the field in bytes, a single value of its base type unless
+    /// the field is sized with [`FitFieldDefinition::array`].
+    fn size(&self) -> u8 {
+        self.base_type().size() as u8
     }
-}
-
-// Start Time, Total Elapsed Time, Total Timer Time, and Timestamp fields are required for all Summary messages.
-pub enum FitLapFieldDefinition {
-    StartTime,
-    StartPositionLat,
-    StartPositionLong,
-    EndPositionLat,
-    EndPositionLong,
-    TotalElapsedTime,
-    TotalTimerTime,
-    TotalDistance,
-    TotalCalories,
-    AverageSpeed,
-    MaxSpeed,
-    AverageHeartRate,
-    MaxHeartRate,
-    Sport,
-    SubSport,
-    Timestamp,
-    MessageIndex,
-}
 
-impl FitFieldDefinition for FitLapFieldDefinition {
+    fn get(&self) -> [u8; 3] {
+        [self.field_number(), self.size(), self.base_type() as u8]
+    }
+
+    /// Scale of the stored value, `stored = (value + offset) * scale`.
+    fn scale(&self) -> f64 {
+        1.0
+    }
+
+    /// Offset of the stored value, `stored = (value + offset) * scale`.
+    fn offset(&self) -> i32 {
+        0
+    }
+
+    /// Units of the value, as written in the FIT Profile.
+    fn units(&self) -> &'static str {
+        ""
+    }
+
+    /// Stored value of the field for a physical `value`, in its
+    /// [units](FitFieldDefinition::units), applying the offset and scale.
+    ///
+    /// Integers are rounded to the nearest value. Returns `None` if the
+    /// field isn't numeric or the result is out of range of its base type.
+    fn stored(&self, value: f64) -> Option<FitValue<'static>> {
+        let stored = (value + self.offset() as f64) * self.scale();
+        let integer = |min: f64, max: f64| {
+            let rounded = if stored < 0.0 {
+                stored - 0.5
+            } else {
+                stored + 0.5
+            };
+            (rounded > min - 1.0 && rounded < max + 1.0).then_some(rounded as i64)
+        };
+
+        let value = match self.base_type() {
+            FitBaseType::Enum => FitValue::Enum(integer(0.0, u8::MAX as f64)? as u8),
+            FitBaseType::Sint8 => FitValue::Sint8(integer(i8::MIN as f64, i8::MAX as f64)? as i8),
+            FitBaseType::Uint8 => FitValue::Uint8(integer(0.0, u8::MAX as f64)? as u8),
+            FitBaseType::Uint8z => FitValue::Uint8z(integer(0.0, u8::MAX as f64)? as u8),
+            FitBaseType::Sint16 => {
+                FitValue::Sint16(integer(i16::MIN as f64, i16::MAX as f64)? as i16)
+            }
+            FitBaseType::Uint16 => FitValue::Uint16(integer(0.0, u16::MAX as f64)? as u16),
+            FitBaseType::Uint16z => FitValue::Uint16z(integer(0.0, u16::MAX as f64)? as u16),
+            FitBaseType::Sint32 => {
+                FitValue::Sint32(integer(i32::MIN as f64, i32::MAX as f64)? as i32)
+            }
+            FitBaseType::Uint32 => FitValue::Uint32(integer(0.0, u32::MAX as f64)? as u32),
+            FitBaseType::Uint32z => FitValue::Uint32z(integer(0.0, u32::MAX as f64)? as u32),
+            FitBaseType::Sint64 => FitValue::Sint64(integer(i64::MIN as f64, i64::MAX as f64)?),
+            FitBaseType::Uint64 => FitValue::Uint64(integer(0.0, i64::MAX as f64)? as u64),
+            FitBaseType::Uint64z => FitValue::Uint64z(integer(0.0, i64::MAX as f64)? as u64),
+            FitBaseType::Float32 => FitValue::Float32(stored as f32),
+            FitBaseType::Float64 => FitValue::Float64(stored),
+            FitBaseType::String | FitBaseType::Byte => return None,
+        };
+        Some(value)
+    }
+
+    /// Physical value, in the [units](FitFieldDefinition::units) of the
+    /// field, of a value stored in it, removing the scale and offset.
+    ///
+    /// Returns `None` for values that aren't numbers, or are invalid.
+    fn physical(&self, value: &FitValue) -> Option<f64> {
+        let stored = match *value {
+            FitValue::Enum(v) | FitValue::Uint8(v) | FitValue::Uint8z(v) => v as f64,
+            FitValue::Sint8(v) => v as f64,
+            FitValue::Sint16(v) => v as f64,
+            FitValue::Uint16(v) | FitValue::Uint16z(v) => v as f64,
+            FitValue::Sint32(v) => v as f64,
+            FitValue::Uint32(v) | FitValue::Uint32z(v) => v as f64,
+            FitValue::Sint64(v) => v as f64,
+            FitValue::Uint64(v) | FitValue::Uint64z(v) => v as f64,
+            FitValue::Float32(v) => v as f64,
+            FitValue::Float64(v) => v,
+            FitValue::String(_) | FitValue::Byte(_) | FitValue::Array(_) | FitValue::Invalid(_) => {
+                return None
+            }
+        };
+        Some(stored / self.scale() - self.offset() as f64)
+    }
+
+    /// Field holding an array of `count` values of its base type.
+    ///
+    /// Fails with [`FitError::FieldTooLarge`] past 255 bytes.
+    fn array(self, count: u8) -> Result<FitSizedField<Self>, FitError>
+    where
+        Self: Sized,
+    {
+        let size = u8::try_from(self.base_type().size() * count as usize)
+            .map_err(|_e| FitError::FieldTooLarge)?;
+        Ok(FitSizedField { field: self, size })
+    }
+
+    /// String field of `len` bytes, including its null terminator. Shorter
+    /// strings are padded with zeros.
+    ///
+    /// Fails with [`FitError::FieldTooSmall`] for 0 bytes.
+    fn string(self, len: u8) -> Result<FitSizedField<Self>, FitError>
+    where
+        Self: Sized,
+    {
+        if len == 0 {
+            return Err(FitError::FieldTooSmall);
+        }
+        Ok(FitSizedField {
+            field: self,
+            size: len,
+        })
+    }
+}
+
+/// Field definition with an explicit size, for strings and arrays, returned
+/// by [`FitFieldDefinition::array`] and [`FitFieldDefinition::string`].
+///
+/// Fields of a message all have the same type, so fields of a single value
+/// are converted with `into()` when others need a size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FitSizedField<T> {
+    field: T,
+    size: u8,
+}
+
+impl<T: FitFieldDefinition> FitSizedField<T> {
+    pub fn field(&self) -> &T {
+        &self.field
+    }
+}
+
+impl<T: FitFieldDefinition> From<T> for FitSizedField<T> {
+    fn from(field: T) -> Self {
+        let size = field.size();
+        Self { field, size }
+    }
+}
+
+impl<T: FitFieldDefinition> FitFieldDefinition for FitSizedField<T> {
     fn base_type(&self) -> FitBaseType {
-        match self {
-            Self::StartTime => FitBaseType::Uint32,
-            Self::StartPositionLat => FitBaseType::Sint32,
-            Self::StartPositionLong => FitBaseType::Sint32,
-            Self::EndPositionLat => FitBaseType::Sint32,
-            Self::EndPositionLong => FitBaseType::Sint32,
-            Self::TotalElapsedTime => FitBaseType::Uint32,
-            Self::TotalTimerTime => FitBaseType::Uint32,
-            Self::TotalDistance => FitBaseType::Uint32,
-            Self::TotalCalories => FitBaseType::Uint16,
-            Self::AverageSpeed => FitBaseType::Uint16,
-            Self::MaxSpeed => FitBaseType::Uint16,
-            Self::AverageHeartRate => FitBaseType::Uint8,
-            Self::MaxHeartRate => FitBaseType::Uint8,
-            Self::Sport => FitBaseType::Enum,
-            Self::SubSport => FitBaseType::Enum,
-            Self::Timestamp => FitBaseType::Uint32,
-            Self::MessageIndex => FitBaseType::Uint16,
-        }
+        self.field.base_type()
     }
 
     fn field_number(&self) -> u8 {
-        match self {
-            Self::StartTime => 2,
-            Self::StartPositionLat => 3,
-            Self::StartPositionLong => 4,
-            Self::EndPositionLat => 5,
-            Self::EndPositionLong => 6,
-            Self::TotalElapsedTime => 7,
-            Self::TotalTimerTime => 8,
-            Self::TotalDistance => 9,
-            Self::TotalCalories => 11,
-            Self::AverageSpeed => 13,
-            Self::MaxSpeed => 14,
-            Self::AverageHeartRate => 15,
-            Self::MaxHeartRate => 16,
-            Self::Sport => 25,
-            Self::SubSport => 39,
-            Self::Timestamp => 253,
-            Self::MessageIndex => 254,
-        }
+        self.field.field_number()
     }
-}
-
-pub enum FitEventFieldDefinition {
-    Timestamp,
-    Event,
-    EventType,
-    Data16,
-    Data,
-}
 
-impl FitFieldDefinition for FitEventFieldDefinition {
-    fn base_type(&self) -> FitBaseType {
-        match self {
-            Self::Timestamp => FitBaseType::Uint32,
-            Self::Event => FitBaseType::Enum,
-            Self::EventType => FitBaseType::Enum,
-            Self::Data16 => FitBaseType::Uint16,
-            Self::Data => FitBaseType::Uint32,
-        }
+    fn name(&self) -> &'static str {
+        self.field.name()
     }
 
-    fn field_number(&self) -> u8 {
-        match self {
-            Self::Timestamp => 253,
-            Self::Event => 0,
-            Self::EventType => 1,
-            Self::Data16 => 2,
-            Self::Data => 3,
-        }
+    fn from_field_number(field_number: u8) -> Option<Self> {
+        T::from_field_number(field_number).map(Self::from)
     }
-}
 
-pub enum FitRecordFieldDefinition {
-    PositionLat,
-    PositionLong,
-    Altitude,
-    HeartRate,
-    Speed,
-    Timestamp,
-}
-
-impl FitFieldDefinition for FitRecordFieldDefinition {
-    fn base_type(&self) -> FitBaseType {
-        match self {
-            Self::PositionLat => FitBaseType::Sint32,
-            Self::PositionLong => FitBaseType::Sint32,
-            Self::Altitude => FitBaseType::Uint16,
-            Self::HeartRate => FitBaseType::Uint8,
-            Self::Speed => FitBaseType::Uint16,
-            Self::Timestamp => FitBaseType::Uint32,
-        }
+    fn size(&self) -> u8 {
+        self.size
     }
 
-    fn field_number(&self) -> u8 {
-        match self {
-            Self::PositionLat => 0,
-            Self::PositionLong => 1,
-            Self::Altitude => 2,
-            Self::HeartRate => 3,
-            Self::Speed => 6,
-            Self::Timestamp => 253,
-        }
+    fn scale(&self) -> f64 {
+        self.field.scale()
     }
-}
 
-///////////////////////////
-// WORKOUT FILE SETTINGS //
-///////////////////////////
-
-pub enum FitWorkoutFieldDefinition {
-    MessageIndex,
-    Sport,
-    NumValidSteps, // uint16
-    WorkoutName,
-    SubSport,
-    PoolLength, // in meters
-    PoolLengthUnit,
-    WorkoutDescription,
-}
-
-impl FitFieldDefinition for FitWorkoutFieldDefinition {
-    fn base_type(&self) -> FitBaseType {
-        match self {
-            Self::MessageIndex => FitBaseType::Uint16,
-            Self::Sport => FitBaseType::Enum,
-            Self::NumValidSteps => FitBaseType::Uint16,
-            Self::WorkoutName => FitBaseType::String,
-            Self::SubSport => FitBaseType::Enum,
-            Self::PoolLength => FitBaseType::Uint16,
-            Self::PoolLengthUnit => FitBaseType::Enum,
-            Self::WorkoutDescription => FitBaseType::String,
-        }
+    fn offset(&self) -> i32 {
+        self.field.offset()
     }
 
-    fn field_number(&self) -> u8 {
-        match self {
-            Self::MessageIndex => 254,
-            Self::Sport => 4,
-            Self::NumValidSteps => 6,
-            Self::WorkoutName => 8,
-            Self::SubSport => 11,
-            Self::PoolLength => 14,
-            Self::PoolLengthUnit => 15,
-            Self::WorkoutDescription => 17,
-        }
+    fn units(&self) -> &'static str {
+        self.field.units()
     }
 }
 
-pub enum FitWorkoutStepFieldDefinition {
-    MessageIndex,
-    DurationType,
-    DurationValue,
-    DurationTime,
-    DurationDistance,
-    DurationCalories,
-    DurationStep,
-    TargetType,
-    TargetValue,
+/// Value of a field for a physical value, invalid if missing or out of
+/// range.
+pub(crate) fn stored_value<T: FitFieldDefinition>(
+    field: T,
+    value: Option<f32>,
+) -> (T, FitValue<'static>) {
+    let value = value
+        .and_then(|value| field.stored(value as f64))
+        .unwrap_or(FitValue::Invalid(field.base_type()));
+    (field, value)
 }
 
-impl FitFieldDefinition for FitWorkoutStepFieldDefinition {
-    fn base_type(&self) -> FitBaseType {
-        match self {
-            Self::MessageIndex => FitBaseType::Uint16,
-            Self::DurationType => FitBaseType::Enum,
-            Self::DurationValue => FitBaseType::Uint32,
-            Self::DurationTime => FitBaseType::Uint32,
-            Self::DurationDistance => FitBaseType::Uint32,
-            Self::DurationCalories => FitBaseType::Uint32,
-            Self::DurationStep => FitBaseType::Uint32,
-            Self::TargetType => FitBaseType::Enum,
-            Self::TargetValue => FitBaseType::Uint32,
-        }
-    }
-
-    fn field_number(&self) -> u8 {
-        match self {
-            Self::MessageIndex => 254,
-            Self::DurationType => 1,
-            Self::DurationValue => 2,
-            Self::DurationTime => 2,
-            Self::DurationDistance => 2,
-            Self::DurationCalories => 2,
-            Self::DurationStep => 2,
-            Self::TargetType => 3,
-            Self::TargetValue => 4,
-        }
-    }
-}
+include!(concat!(env!("OUT_DIR"), "/profile.rs"));
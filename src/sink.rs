@@ -0,0 +1,83 @@
+use heapless::Vec;
+
+use crate::fit::FitError;
+
+/// Output a [`FitEncoder`](crate::FitEncoder) writes the file to.
+///
+/// Records are appended as they are encoded, so the file never has to be
+/// held in memory. The header holds the data size and its CRC, which are
+/// only known once the file is done, so the encoder writes a placeholder
+/// header first and patches it with [`FitSink::patch`] at the end.
+pub trait FitSink {
+    /// Appends `bytes` to the output.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), FitError>;
+
+    /// Overwrites bytes already written, `offset` bytes from the start of
+    /// the output.
+    fn patch(&mut self, offset: usize, bytes: &[u8]) -> Result<(), FitError>;
+
+    /// Shortens the output to `len` bytes, to append to a file again once
+    /// it was finalized. Sinks that can't shrink their output fail with
+    /// [`FitError::Sink`].
+    fn truncate(&mut self, len: usize) -> Result<(), FitError> {
+        let _ = len;
+        Err(FitError::Sink)
+    }
+}
+
+/// In-memory output, holding up to `N` bytes.
+impl<const N: usize> FitSink for Vec<u8, N> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), FitError> {
+        self.extend_from_slice(bytes)
+            .map_err(|_e| FitError::BufferFull {
+                needed: self.len() + bytes.len() - N,
+            })
+    }
+
+    fn patch(&mut self, offset: usize, bytes: &[u8]) -> Result<(), FitError> {
+        self.get_mut(offset..offset + bytes.len())
+            .ok_or(FitError::Sink)?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), FitError> {
+        Vec::truncate(self, len);
+        Ok(())
+    }
+}
+
+/// In-memory output, growing as needed.
+#[cfg(feature = "std")]
+impl FitSink for std::vec::Vec<u8> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), FitError> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn patch(&mut self, offset: usize, bytes: &[u8]) -> Result<(), FitError> {
+        self.get_mut(offset..offset + bytes.len())
+            .ok_or(FitError::Sink)?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), FitError> {
+        std::vec::Vec::truncate(self, len);
+        Ok(())
+    }
+}
+
+impl<S: FitSink + ?Sized> FitSink for &mut S {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), FitError> {
+        (**self).write(bytes)
+    }
+
+    fn patch(&mut self, offset: usize, bytes: &[u8]) -> Result<(), FitError> {
+        (**self).patch(offset, bytes)
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), FitError> {
+        (**self).truncate(len)
+    }
+}
@@ -0,0 +1,304 @@
+use core::{fmt, ops::Add};
+
+use crate::{types::FitBaseType, value::FitValue};
+
+/// Seconds between the Unix epoch and the FIT epoch, 1989-12-31T00:00:00Z.
+pub const FIT_EPOCH_UNIX: i64 = 631_065_600;
+
+/// Days between 0000-03-01 and the FIT epoch, in the proleptic Gregorian
+/// calendar.
+const FIT_EPOCH_DAYS: i64 = 726_772;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// FIT `date_time`, seconds since the FIT epoch, 1989-12-31T00:00:00Z.
+///
+/// Values below [`FitDateTime::MIN`] aren't dates, but seconds relative to
+/// an arbitrary system time, e.g. the time since the device was powered on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FitDateTime(u32);
+
+/// FIT `local_date_time`, seconds since the FIT epoch in local time, as
+/// written in Activity `local_timestamp`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FitLocalDateTime(u32);
+
+/// Calendar date and time of day, in the proleptic Gregorian calendar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FitCalendarTime {
+    pub year: u16,
+    /// Month, from 1 to 12.
+    pub month: u8,
+    /// Day of the month, from 1.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl FitDateTime {
+    /// Smallest value that is a date, smaller values are system times.
+    pub const MIN: u32 = 0x1000_0000;
+
+    /// Date time of `seconds` since the FIT epoch, or relative system time
+    /// if below [`FitDateTime::MIN`].
+    pub const fn new(seconds: u32) -> Self {
+        Self(seconds)
+    }
+
+    /// Date time of seconds since the Unix epoch.
+    ///
+    /// Returns `None` for times that can't be written as a FIT date, before
+    /// 1998-07-05 or after 2126-02-06.
+    pub fn from_unix(unix: i64) -> Option<Self> {
+        let seconds = u32::try_from(unix.checked_sub(FIT_EPOCH_UNIX)?).ok()?;
+        (seconds >= Self::MIN).then_some(Self(seconds))
+    }
+
+    /// Date time of a UTC calendar time, `None` if it isn't a valid date or
+    /// can't be written as a FIT date.
+    pub fn from_utc(time: &FitCalendarTime) -> Option<Self> {
+        Self::from_unix(time.fit_seconds()? + FIT_EPOCH_UNIX)
+    }
+
+    /// Date time of an ISO 8601 date and time, as in GPX files, e.g.
+    /// `2025-09-27T16:10:30Z` or `2025-09-27T18:10:30.250+02:00`.
+    ///
+    /// Fractions of a second are dropped, and times without a zone are
+    /// taken as UTC. Returns `None` if the text isn't such a time or can't
+    /// be written as a FIT date.
+    pub fn from_iso8601(text: &str) -> Option<Self> {
+        let (time, zone) = match text.find(['Z', 'z', '+']) {
+            Some(index) => text.split_at(index),
+            // A minus after the time is the zone, not the date separator.
+            None => match text.rfind('-').filter(|&index| index > 10) {
+                Some(index) => text.split_at(index),
+                None => (text, ""),
+            },
+        };
+        let time = time.split_once('.').map_or(time, |(time, _)| time);
+        let bytes = time.as_bytes();
+        if bytes.len() != 19
+            || bytes[4] != b'-'
+            || bytes[7] != b'-'
+            || !matches!(bytes[10], b'T' | b't' | b' ')
+            || bytes[13] != b':'
+            || bytes[16] != b':'
+        {
+            return None;
+        }
+        let calendar = FitCalendarTime {
+            year: time.get(0..4)?.parse().ok()?,
+            month: time.get(5..7)?.parse().ok()?,
+            day: time.get(8..10)?.parse().ok()?,
+            hour: time.get(11..13)?.parse().ok()?,
+            minute: time.get(14..16)?.parse().ok()?,
+            second: time.get(17..19)?.parse().ok()?,
+        };
+        let offset = match zone {
+            "" | "Z" | "z" => 0,
+            _ => {
+                let (hours, minutes) = zone.get(1..)?.split_once(':')?;
+                let hours: i64 = hours.parse().ok()?;
+                let minutes: i64 = minutes.parse().ok()?;
+                if hours > 23 || minutes > 59 {
+                    return None;
+                }
+                let offset = hours * 3600 + minutes * 60;
+                if zone.starts_with('-') {
+                    -offset
+                } else {
+                    offset
+                }
+            }
+        };
+        Self::from_unix(calendar.fit_seconds()? + FIT_EPOCH_UNIX - offset)
+    }
+
+    /// Seconds since the FIT epoch, as stored in the file.
+    pub const fn seconds(&self) -> u32 {
+        self.0
+    }
+
+    /// Whether the value is relative to a system time instead of a date.
+    pub const fn is_system_time(&self) -> bool {
+        self.0 < Self::MIN
+    }
+
+    /// Seconds since the Unix epoch, `None` for system times.
+    pub fn to_unix(&self) -> Option<i64> {
+        (!self.is_system_time()).then_some(self.0 as i64 + FIT_EPOCH_UNIX)
+    }
+
+    /// UTC calendar time, `None` for system times.
+    pub fn to_utc(&self) -> Option<FitCalendarTime> {
+        (!self.is_system_time()).then(|| FitCalendarTime::from_fit_seconds(self.0 as i64))
+    }
+
+    /// Date time `seconds` later, `None` past the last time a FIT date can
+    /// hold.
+    pub fn checked_add(&self, seconds: u32) -> Option<Self> {
+        self.0.checked_add(seconds).map(Self)
+    }
+
+    /// Local time, `utc_offset` seconds ahead of UTC.
+    ///
+    /// Returns `None` for system times, which have no time zone.
+    pub fn to_local(&self, utc_offset: i32) -> Option<FitLocalDateTime> {
+        if self.is_system_time() {
+            return None;
+        }
+        let local = u32::try_from(self.0 as i64 + utc_offset as i64).ok()?;
+        Some(FitLocalDateTime(local))
+    }
+}
+
+impl FitLocalDateTime {
+    /// Local date time of `seconds` since the FIT epoch.
+    pub const fn new(seconds: u32) -> Self {
+        Self(seconds)
+    }
+
+    /// Local date time of a calendar time, `None` if it isn't a valid date
+    /// or can't be written as a FIT date.
+    pub fn from_calendar(time: &FitCalendarTime) -> Option<Self> {
+        u32::try_from(time.fit_seconds()?).ok().map(Self)
+    }
+
+    /// Local date time `seconds` later, `None` past the last time a FIT date
+    /// can hold.
+    pub fn checked_add(&self, seconds: u32) -> Option<Self> {
+        self.0.checked_add(seconds).map(Self)
+    }
+
+    /// Seconds since the FIT epoch in local time, as stored in the file.
+    pub const fn seconds(&self) -> u32 {
+        self.0
+    }
+
+    /// Local calendar time.
+    pub fn to_calendar(&self) -> FitCalendarTime {
+        FitCalendarTime::from_fit_seconds(self.0 as i64)
+    }
+
+    /// Offset of the local time from UTC in seconds, given the same moment
+    /// in UTC, as used to find the time zone of an Activity.
+    pub fn utc_offset(&self, utc: FitDateTime) -> i32 {
+        (self.0 as i64 - utc.0 as i64) as i32
+    }
+}
+
+impl FitCalendarTime {
+    /// Seconds since the FIT epoch, `None` if the date isn't valid.
+    fn fit_seconds(&self) -> Option<i64> {
+        let leap = self.year.is_multiple_of(4)
+            && (!self.year.is_multiple_of(100) || self.year.is_multiple_of(400));
+        let days_in_month = match self.month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => return None,
+        };
+        if self.day == 0 || self.day > days_in_month {
+            return None;
+        }
+        if self.hour > 23 || self.minute > 59 || self.second > 59 {
+            return None;
+        }
+
+        // Days since 0000-03-01, years starting in March put leap days last.
+        let (year, month) = match self.month {
+            1 | 2 => (self.year as i64 - 1, self.month as i64 + 9),
+            _ => (self.year as i64, self.month as i64 - 3),
+        };
+        let days = 365 * year + year.div_euclid(4) - year.div_euclid(100)
+            + year.div_euclid(400)
+            + (153 * month + 2) / 5
+            + self.day as i64
+            - 1;
+
+        let time = self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        Some((days - FIT_EPOCH_DAYS) * SECONDS_PER_DAY + time)
+    }
+
+    fn from_fit_seconds(seconds: i64) -> Self {
+        let days = seconds.div_euclid(SECONDS_PER_DAY) + FIT_EPOCH_DAYS;
+        let time = seconds.rem_euclid(SECONDS_PER_DAY);
+
+        // Inverse of the days in `fit_seconds`, by 400 years eras.
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let (year, month) = match month {
+            10 | 11 => (era * 400 + year_of_era + 1, month - 9),
+            _ => (era * 400 + year_of_era, month + 3),
+        };
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+}
+
+/// ISO 8601 date and time, e.g. `2025-09-27T16:10:30`, without a zone.
+impl fmt::Display for FitCalendarTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Adds seconds, saturating at the last time a FIT date can hold.
+impl Add<u32> for FitDateTime {
+    type Output = Self;
+
+    fn add(self, seconds: u32) -> Self {
+        Self(self.0.saturating_add(seconds))
+    }
+}
+
+/// Adds seconds, saturating at the last time a FIT date can hold.
+impl Add<u32> for FitLocalDateTime {
+    type Output = Self;
+
+    fn add(self, seconds: u32) -> Self {
+        Self(self.0.saturating_add(seconds))
+    }
+}
+
+impl From<FitDateTime> for FitValue<'_> {
+    fn from(value: FitDateTime) -> Self {
+        Self::Uint32(value.0)
+    }
+}
+
+impl From<Option<FitDateTime>> for FitValue<'_> {
+    fn from(value: Option<FitDateTime>) -> Self {
+        value.map_or(Self::Invalid(FitBaseType::Uint32), Self::from)
+    }
+}
+
+impl From<FitLocalDateTime> for FitValue<'_> {
+    fn from(value: FitLocalDateTime) -> Self {
+        Self::Uint32(value.0)
+    }
+}
+
+impl From<Option<FitLocalDateTime>> for FitValue<'_> {
+    fn from(value: Option<FitLocalDateTime>) -> Self {
+        value.map_or(Self::Invalid(FitBaseType::Uint32), Self::from)
+    }
+}
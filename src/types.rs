@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
-#[derive(Clone, Copy)]
+use crate::{fit::FitMessageArchitecture, value::arch_bytes};
+
+/// Base type number bit flagging types with a byte order.
+const ENDIAN_ABILITY: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FitBaseType {
     Enum,
     Sint8,
@@ -31,222 +36,129 @@ impl FitBaseType {
             Self::String => 1, // Variable.
         }
     }
-}
 
-#[derive(Debug)]
-pub enum FitFileType {
-    Device = 1,
-    Settings,
-    Sport,
-    Activity,
-    Workout,
-    Course,
-}
+    /// Invalid value of the base type, marking a missing value, as the bits
+    /// of a value of its size. Strings are invalid when empty.
+    pub fn invalid(&self) -> u64 {
+        match self {
+            Self::Enum | Self::Uint8 | Self::Byte => 0xFF,
+            Self::Sint8 => 0x7F,
+            Self::Sint16 => 0x7FFF,
+            Self::Uint16 => 0xFFFF,
+            Self::Sint32 => 0x7FFF_FFFF,
+            Self::Uint32 | Self::Float32 => 0xFFFF_FFFF,
+            Self::Sint64 => 0x7FFF_FFFF_FFFF_FFFF,
+            Self::Uint64 | Self::Float64 => 0xFFFF_FFFF_FFFF_FFFF,
+            Self::String | Self::Uint8z | Self::Uint16z | Self::Uint32z | Self::Uint64z => 0,
+        }
+    }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum FitFileManufacturerType {
-    Garmin = 1,
-    Zephyr = 3,
-    Development = 255,
-    Decathlon = 310,
-}
+    /// Whether values of the base type have a byte order, flagged by the
+    /// endian ability bit of its number.
+    pub fn is_endian(&self) -> bool {
+        *self as u8 & ENDIAN_ABILITY != 0
+    }
 
-#[derive(Debug)]
-pub enum FitGlobalMessageType {
-    FileId,
-    Capabilities,
-    Session = 18,
-    Lap = 19,
-    Record = 20,
-    Event = 21,
-    Workout = 26,
-    WorkoutStep = 27,
-    Activity = 34,
-}
+    /// Writes the invalid value into every value of `buffer`.
+    pub(crate) fn encode_invalid(&self, arch: FitMessageArchitecture, buffer: &mut [u8]) {
+        let invalid = arch_bytes(arch, self.invalid().to_le_bytes());
+        let invalid = match arch {
+            FitMessageArchitecture::LSB => &invalid[..self.size()],
+            FitMessageArchitecture::MSB => &invalid[8 - self.size()..],
+        };
+        for value in buffer.chunks_mut(self.size()) {
+            value.copy_from_slice(&invalid[..value.len()]);
+        }
+    }
 
-pub enum FitActivityType {
-    Manual,
-    AutoMultiSport,
+    /// Whether `data` only holds invalid values.
+    pub(crate) fn is_invalid(&self, arch: FitMessageArchitecture, data: &[u8]) -> bool {
+        if *self == Self::String {
+            return data.first().is_none_or(|b| *b == 0);
+        }
+        let mut invalid = [0u8; 8];
+        self.encode_invalid(arch, &mut invalid[..self.size()]);
+        !data.is_empty()
+            && data
+                .chunks(self.size())
+                .all(|value| value == &invalid[..self.size()])
+    }
 }
 
-pub enum FitEventType {
-    Timer,
-    Workout = 3,
-    WorkoutStep,
-    PowerDown,
-    PowerUp,
-    OffCourse,
-    Session,
-    Lap,
-    CoursePoint,
-    Battery,
-    Activity = 26,
-    Length = 28,
-}
+impl TryFrom<u8> for FitBaseType {
+    type Error = u8;
 
-pub enum FitEventTypeType {
-    Start,
-    Stop,
-    ConsecutiveDepreciated,
-    Marker,
-    StopAll,
-    BeginDepreciated,
-    EndDepreciated,
-    EndAllDepreciated,
-    StopDisable,
-    StopDisableAll,
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Enum),
+            1 => Ok(Self::Sint8),
+            2 => Ok(Self::Uint8),
+            131 => Ok(Self::Sint16),
+            132 => Ok(Self::Uint16),
+            133 => Ok(Self::Sint32),
+            134 => Ok(Self::Uint32),
+            7 => Ok(Self::String),
+            136 => Ok(Self::Float32),
+            137 => Ok(Self::Float64),
+            10 => Ok(Self::Uint8z),
+            139 => Ok(Self::Uint16z),
+            140 => Ok(Self::Uint32z),
+            13 => Ok(Self::Byte),
+            142 => Ok(Self::Sint64),
+            143 => Ok(Self::Uint64),
+            144 => Ok(Self::Uint64z),
+            _ => Err(value),
+        }
+    }
 }
 
-#[derive(Debug)]
-pub enum FitSportType {
-    Generic,
-    Running,
-    Cycling,
-    Transition,
-    FitnessEquipment,
-    Swimming,
-    Basketball,
-    Soccer,
-    Tennis,
-    AmericanFootball,
-    Training,
-    Walking,
-    CrossCountrySkiing,
-    AlpineSkiing,
-    Snowboarding,
-    Rowing,
-    Mountaineering,
-    Hiking,
-    Multisport,
-    Paddling,
-    Flying,
-    EBiking,
-    Motorcycling,
-    Boating,
-    Driving,
-    Golf,
-    HangGliding,
-    HorsebackRiding,
-    Hunting,
-    Fishing,
-    InlineSkating,
-    RockClimbing,
-    Sailing,
-    IceSkating,
-    SkyDiving,
-    Snowshoeing,
-    Snowmobiling,
-    StandUpPaddleboarding,
-    Surfing,
-    Wakeboarding,
-    WaterSkiing,
-    Kayaking,
-    Rafting,
-    Windsurfing,
-    Kitesurfing,
-    Tactical,
-    Jumpmaster,
-    Boxing,
-    All = 254,
-}
+macro_rules! fit_manufacturers {
+    ($($variant:ident = $value:literal, $name:literal;)*) => {
+        /// Manufacturer of a device, as listed in the FIT Profile.
+        #[derive(Debug, Copy, Clone, PartialEq)]
+        pub enum FitFileManufacturerType {
+            $($variant,)*
+            /// Manufacturer missing from the profile.
+            Unknown(u16),
+        }
 
-#[derive(Debug)]
-pub enum FitSubSportType {
-    Generic,
-    Treadmill,
-    Street,
-    Trail,
-    Track,
-    Spin,
-    IndoorCycling,
-    Road,
-    Mountain,
-    Downhill,
-    Recumbent,
-    Cyclocross,
-    HandCycling,
-    TrackCycling,
-    IndoorRowing,
-    Elliptical,
-    StairClimbing,
-    LapSwimming,
-    OpenWater,
-    FlexibilityTraining,
-    StrengthTraining,
-    WarmUp,
-    Match,
-    Exercise,
-    Challenge,
-    IndoorSkiing,
-    CardioTraining,
-    IndoorWalking,
-    EBikeFitness,
-    Bmx,
-    CasualWalking,
-    SpeedWalking,
-    BikeToRunTransition,
-    RunToBikeTransition,
-    SwimToBikeTransition,
-    Atv,
-    Motocross,
-    Backcountry,
-    Resort,
-    RcDrone,
-    Wingsuit,
-    Whitewater,
-    SkateSkiing,
-    Yoga,
-    Pilates,
-    IndoorRunning,
-    GravelCycling,
-    EBikeMountain,
-    Commuting,
-    MixedSurface,
-    Navigate,
-    TrackMe,
-    Map,
-    SingleGasDiving,
-    MultiGasDiving,
-    GaugeDiving,
-    ApneaDiving,
-    ApneaHunting,
-    VirtualActivity,
-    Obstacle,
-}
+        impl FitFileManufacturerType {
+            /// Manufacturer name as written in the FIT Profile.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$variant => $name,)*
+                    Self::Unknown(_) => "unknown",
+                }
+            }
+        }
 
-#[derive(Debug)]
-pub enum FitWorkoutStepDuration {
-    // wkt_step_duration
-    Time,
-    Distance,
-    HrLessThan,
-    HrGreaterThan,
-    Calories,
-    Open,
-}
+        impl From<u16> for FitFileManufacturerType {
+            fn from(value: u16) -> Self {
+                match value {
+                    $($value => Self::$variant,)*
+                    value => Self::Unknown(value),
+                }
+            }
+        }
 
-#[derive(Debug)]
-pub enum FitWorkoutStepTarget {
-    // wkt_step_target
-    Speed,
-    HeartRate,
-    Open,
-    Cadence,
-    Power,
-    Grade,
-    Resistance,
-    Power3s,
-    Power10s,
-    Power30s,
-    PowerLap,
-    SwimStroke,
-    SpeedLap,
-    HeartRateLap,
+        impl From<FitFileManufacturerType> for u16 {
+            fn from(manufacturer: FitFileManufacturerType) -> Self {
+                match manufacturer {
+                    $(FitFileManufacturerType::$variant => $value,)*
+                    FitFileManufacturerType::Unknown(value) => value,
+                }
+            }
+        }
+    };
 }
 
-#[derive(Debug)]
-pub enum FitDisplayMeasureType {
-    Metric,
-    Statue,
-    Nautical,
+impl core::fmt::Display for FitFileManufacturerType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Unknown(value) => write!(f, "unknown ({value})"),
+            _ => f.write_str(self.name()),
+        }
+    }
 }
+
+include!(concat!(env!("OUT_DIR"), "/types.rs"));
@@ -0,0 +1,358 @@
+use crate::{fit::FitMessageArchitecture, types::FitBaseType};
+
+/// A single field value, typed by its FIT base type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FitValue<'a> {
+    Enum(u8),
+    Sint8(i8),
+    Uint8(u8),
+    Sint16(i16),
+    Uint16(u16),
+    Sint32(i32),
+    Uint32(u32),
+    String(&'a str),
+    Float32(f32),
+    Float64(f64),
+    Uint8z(u8),
+    Uint16z(u16),
+    Uint32z(u32),
+    Byte(&'a [u8]),
+    Sint64(i64),
+    Uint64(u64),
+    Uint64z(u64),
+    /// Several values of a base type, for array fields.
+    Array(FitArray<'a>),
+    /// Missing value of a base type, written as the invalid value of the
+    /// field, see [`FitBaseType::invalid`]. `None` options convert to it.
+    Invalid(FitBaseType),
+}
+
+/// Array of values of a single base type, as encoded in a field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FitArray<'a> {
+    base_type: FitBaseType,
+    arch: FitMessageArchitecture,
+    data: &'a [u8],
+}
+
+impl<'a> FitArray<'a> {
+    /// Array of the values of `base_type` encoded in `data`, in the byte
+    /// order of `arch`.
+    ///
+    /// Returns `None` for strings and byte arrays, which are values of their
+    /// own, or if the data doesn't hold a whole number of values.
+    pub fn new(
+        base_type: FitBaseType,
+        arch: FitMessageArchitecture,
+        data: &'a [u8],
+    ) -> Option<Self> {
+        if matches!(base_type, FitBaseType::String | FitBaseType::Byte)
+            || !data.len().is_multiple_of(base_type.size())
+        {
+            return None;
+        }
+        Some(Self {
+            base_type,
+            arch,
+            data,
+        })
+    }
+
+    pub fn base_type(&self) -> FitBaseType {
+        self.base_type
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() / self.base_type.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Value at `index`.
+    pub fn get(&self, index: usize) -> Option<FitValue<'a>> {
+        let size = self.base_type.size();
+        let data = self.data.get(index * size..(index + 1) * size)?;
+        FitValue::decode(self.base_type, self.arch, data)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = FitValue<'a>> + '_ {
+        (0..self.len()).filter_map(|index| self.get(index))
+    }
+}
+
+impl<'a> FitValue<'a> {
+    /// Decodes a value of `base_type` from the bytes of a field.
+    ///
+    /// Returns `None` if the bytes don't hold exactly one value of the base
+    /// type, or if a string isn't valid UTF-8.
+    pub fn decode(
+        base_type: FitBaseType,
+        arch: FitMessageArchitecture,
+        data: &'a [u8],
+    ) -> Option<Self> {
+        match base_type {
+            FitBaseType::String => {
+                let len = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+                return core::str::from_utf8(&data[..len]).ok().map(Self::String);
+            }
+            FitBaseType::Byte => return Some(Self::Byte(data)),
+            _ => {}
+        }
+
+        if data.len() != base_type.size() {
+            return FitArray::new(base_type, arch, data)
+                .filter(|array| array.len() > 1)
+                .map(Self::Array);
+        }
+
+        let value = match base_type {
+            FitBaseType::Enum => Self::Enum(data[0]),
+            FitBaseType::Sint8 => Self::Sint8(data[0] as i8),
+            FitBaseType::Uint8 => Self::Uint8(data[0]),
+            FitBaseType::Uint8z => Self::Uint8z(data[0]),
+            FitBaseType::Sint16 => Self::Sint16(i16::from_le_bytes(le_bytes(arch, data))),
+            FitBaseType::Uint16 => Self::Uint16(u16::from_le_bytes(le_bytes(arch, data))),
+            FitBaseType::Uint16z => Self::Uint16z(u16::from_le_bytes(le_bytes(arch, data))),
+            FitBaseType::Sint32 => Self::Sint32(i32::from_le_bytes(le_bytes(arch, data))),
+            FitBaseType::Uint32 => Self::Uint32(u32::from_le_bytes(le_bytes(arch, data))),
+            FitBaseType::Uint32z => Self::Uint32z(u32::from_le_bytes(le_bytes(arch, data))),
+            FitBaseType::Float32 => Self::Float32(f32::from_le_bytes(le_bytes(arch, data))),
+            FitBaseType::Float64 => Self::Float64(f64::from_le_bytes(le_bytes(arch, data))),
+            FitBaseType::Sint64 => Self::Sint64(i64::from_le_bytes(le_bytes(arch, data))),
+            FitBaseType::Uint64 => Self::Uint64(u64::from_le_bytes(le_bytes(arch, data))),
+            FitBaseType::Uint64z => Self::Uint64z(u64::from_le_bytes(le_bytes(arch, data))),
+            FitBaseType::String | FitBaseType::Byte => unreachable!(),
+        };
+        Some(value)
+    }
+
+    /// Base type the value is encoded as.
+    pub fn base_type(&self) -> FitBaseType {
+        match self {
+            Self::Enum(_) => FitBaseType::Enum,
+            Self::Sint8(_) => FitBaseType::Sint8,
+            Self::Uint8(_) => FitBaseType::Uint8,
+            Self::Sint16(_) => FitBaseType::Sint16,
+            Self::Uint16(_) => FitBaseType::Uint16,
+            Self::Sint32(_) => FitBaseType::Sint32,
+            Self::Uint32(_) => FitBaseType::Uint32,
+            Self::String(_) => FitBaseType::String,
+            Self::Float32(_) => FitBaseType::Float32,
+            Self::Float64(_) => FitBaseType::Float64,
+            Self::Uint8z(_) => FitBaseType::Uint8z,
+            Self::Uint16z(_) => FitBaseType::Uint16z,
+            Self::Uint32z(_) => FitBaseType::Uint32z,
+            Self::Byte(_) => FitBaseType::Byte,
+            Self::Sint64(_) => FitBaseType::Sint64,
+            Self::Uint64(_) => FitBaseType::Uint64,
+            Self::Uint64z(_) => FitBaseType::Uint64z,
+            Self::Array(array) => array.base_type,
+            Self::Invalid(base_type) => *base_type,
+        }
+    }
+
+    /// Whether the value can be encoded in a field of `base_type` and `size`
+    /// bytes.
+    ///
+    /// Unsigned integers can be written to any unsigned field of the same
+    /// size, as enums and `z` types share their encoding. Strings need room
+    /// for their null terminator, and arrays must fill the field.
+    pub fn fits(&self, base_type: FitBaseType, size: usize) -> bool {
+        let unsigned = |t: FitBaseType| {
+            matches!(
+                t,
+                FitBaseType::Enum
+                    | FitBaseType::Uint8
+                    | FitBaseType::Uint8z
+                    | FitBaseType::Uint16
+                    | FitBaseType::Uint16z
+                    | FitBaseType::Uint32
+                    | FitBaseType::Uint32z
+                    | FitBaseType::Uint64
+                    | FitBaseType::Uint64z
+            )
+        };
+        let value_type = self.base_type();
+        let compatible = value_type == base_type
+            || (unsigned(value_type)
+                && unsigned(base_type)
+                && value_type.size() == base_type.size());
+
+        match (self, base_type) {
+            (Self::String(s), FitBaseType::String) => s.len() < size,
+            (Self::Byte(b), FitBaseType::Byte) => b.len() == size,
+            (Self::String(_) | Self::Byte(_), _) => false,
+            (Self::Array(array), _) => compatible && array.data.len() == size,
+            (Self::Invalid(_), _) => {
+                compatible && size > 0 && size.is_multiple_of(base_type.size())
+            }
+            _ => compatible && size == base_type.size(),
+        }
+    }
+
+    /// Encodes the value into `buffer`, in the byte order of `arch`.
+    ///
+    /// The buffer must be the size of the field, see [`FitValue::fits`].
+    /// Strings are null terminated and padded with zeros.
+    pub fn encode(&self, arch: FitMessageArchitecture, buffer: &mut [u8]) {
+        match self {
+            Self::Enum(v) | Self::Uint8(v) | Self::Uint8z(v) => buffer[0] = *v,
+            Self::Sint8(v) => buffer[0] = *v as u8,
+            Self::Sint16(v) => buffer.copy_from_slice(&arch_bytes(arch, v.to_le_bytes())),
+            Self::Uint16(v) | Self::Uint16z(v) => {
+                buffer.copy_from_slice(&arch_bytes(arch, v.to_le_bytes()))
+            }
+            Self::Sint32(v) => buffer.copy_from_slice(&arch_bytes(arch, v.to_le_bytes())),
+            Self::Uint32(v) | Self::Uint32z(v) => {
+                buffer.copy_from_slice(&arch_bytes(arch, v.to_le_bytes()))
+            }
+            Self::Float32(v) => buffer.copy_from_slice(&arch_bytes(arch, v.to_le_bytes())),
+            Self::Float64(v) => buffer.copy_from_slice(&arch_bytes(arch, v.to_le_bytes())),
+            Self::Sint64(v) => buffer.copy_from_slice(&arch_bytes(arch, v.to_le_bytes())),
+            Self::Uint64(v) | Self::Uint64z(v) => {
+                buffer.copy_from_slice(&arch_bytes(arch, v.to_le_bytes()))
+            }
+            Self::String(s) => {
+                buffer.fill(0);
+                buffer[..s.len()].copy_from_slice(s.as_bytes());
+            }
+            Self::Byte(b) => buffer.copy_from_slice(b),
+            Self::Invalid(base_type) => base_type.encode_invalid(arch, buffer),
+            Self::Array(array) => {
+                buffer.copy_from_slice(array.data);
+                if array.arch != arch {
+                    for value in buffer.chunks_mut(array.base_type.size()) {
+                        value.reverse();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl From<i8> for FitValue<'_> {
+    fn from(value: i8) -> Self {
+        Self::Sint8(value)
+    }
+}
+
+impl From<u8> for FitValue<'_> {
+    fn from(value: u8) -> Self {
+        Self::Uint8(value)
+    }
+}
+
+impl From<i16> for FitValue<'_> {
+    fn from(value: i16) -> Self {
+        Self::Sint16(value)
+    }
+}
+
+impl From<u16> for FitValue<'_> {
+    fn from(value: u16) -> Self {
+        Self::Uint16(value)
+    }
+}
+
+impl From<i32> for FitValue<'_> {
+    fn from(value: i32) -> Self {
+        Self::Sint32(value)
+    }
+}
+
+impl From<u32> for FitValue<'_> {
+    fn from(value: u32) -> Self {
+        Self::Uint32(value)
+    }
+}
+
+impl From<i64> for FitValue<'_> {
+    fn from(value: i64) -> Self {
+        Self::Sint64(value)
+    }
+}
+
+impl From<u64> for FitValue<'_> {
+    fn from(value: u64) -> Self {
+        Self::Uint64(value)
+    }
+}
+
+impl From<f32> for FitValue<'_> {
+    fn from(value: f32) -> Self {
+        Self::Float32(value)
+    }
+}
+
+impl From<f64> for FitValue<'_> {
+    fn from(value: f64) -> Self {
+        Self::Float64(value)
+    }
+}
+
+impl<'a> From<&'a str> for FitValue<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::String(value)
+    }
+}
+
+impl<'a> From<FitArray<'a>> for FitValue<'a> {
+    fn from(value: FitArray<'a>) -> Self {
+        Self::Array(value)
+    }
+}
+
+impl<'a> From<&'a [u8]> for FitValue<'a> {
+    fn from(value: &'a [u8]) -> Self {
+        Self::Byte(value)
+    }
+}
+
+macro_rules! from_option {
+    ($($type:ty => $base_type:ident),* $(,)?) => {
+        $(
+            impl<'a> From<Option<$type>> for FitValue<'a> {
+                fn from(value: Option<$type>) -> Self {
+                    value.map_or(Self::Invalid(FitBaseType::$base_type), Self::from)
+                }
+            }
+        )*
+    };
+}
+
+from_option! {
+    i8 => Sint8,
+    u8 => Uint8,
+    i16 => Sint16,
+    u16 => Uint16,
+    i32 => Sint32,
+    u32 => Uint32,
+    i64 => Sint64,
+    u64 => Uint64,
+    f32 => Float32,
+    f64 => Float64,
+    &'a str => String,
+}
+
+/// Copies the bytes of a value into little endian order.
+pub(crate) fn le_bytes<const N: usize>(arch: FitMessageArchitecture, data: &[u8]) -> [u8; N] {
+    let mut bytes = [0u8; N];
+    bytes.copy_from_slice(data);
+    if arch == FitMessageArchitecture::MSB {
+        bytes.reverse();
+    }
+    bytes
+}
+
+/// Reorders the little endian bytes of a value into the architecture order.
+pub(crate) fn arch_bytes<const N: usize>(
+    arch: FitMessageArchitecture,
+    mut bytes: [u8; N],
+) -> [u8; N] {
+    if arch == FitMessageArchitecture::MSB {
+        bytes.reverse();
+    }
+    bytes
+}
@@ -0,0 +1,467 @@
+use heapless::Vec;
+
+use crate::{
+    fit::{truncate, FitEncoder, FitError},
+    profile::{
+        stored_value, FitFieldDefinition, FitWorkoutFieldDefinition, FitWorkoutStepFieldDefinition,
+    },
+    sink::FitSink,
+    types::{
+        FitGlobalMessageType, FitIntensityType, FitSportType, FitWorkoutStepDuration,
+        FitWorkoutStepTarget,
+    },
+    value::FitValue,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum WorkoutError {
+    /// Workout has more steps than the builder can hold.
+    TooManySteps,
+    /// Repeat of no steps, or done no times.
+    EmptyRepeat,
+    Fit(FitError),
+}
+
+/// When a workout step ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkoutDuration {
+    /// Until the step is skipped.
+    Open,
+    /// Time in seconds.
+    Time(f32),
+    /// Distance in m.
+    Distance(f32),
+    Calories(u32),
+    HeartRateLessThan(WorkoutHeartRate),
+    HeartRateGreaterThan(WorkoutHeartRate),
+    PowerLessThan(WorkoutPower),
+    PowerGreaterThan(WorkoutPower),
+}
+
+/// What to aim for during a workout step, either a zone of the athlete or
+/// a custom range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkoutTarget {
+    Open,
+    HeartRateZone(u8),
+    HeartRate {
+        low: WorkoutHeartRate,
+        high: WorkoutHeartRate,
+    },
+    PowerZone(u8),
+    Power {
+        low: WorkoutPower,
+        high: WorkoutPower,
+    },
+    SpeedZone(u8),
+    /// Speed range in m/s.
+    Speed {
+        low: f32,
+        high: f32,
+    },
+    /// Cadence range in rpm.
+    Cadence {
+        low: u32,
+        high: u32,
+    },
+}
+
+/// Heart rate of a workout step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkoutHeartRate {
+    /// Percent of the maximum heart rate.
+    Percent(u8),
+    Bpm(u8),
+}
+
+/// Power of a workout step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkoutPower {
+    /// Percent of the functional threshold power.
+    Percent(u16),
+    Watts(u16),
+}
+
+/// Step of a workout, with an optional name and notes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorkoutStep<'a> {
+    intensity: FitIntensityType,
+    duration: WorkoutDuration,
+    target: WorkoutTarget,
+    name: Option<&'a str>,
+    notes: Option<&'a str>,
+}
+
+/// Builds the Workout and Workout Step messages of a Workout file.
+///
+/// Repeated steps are followed by a `repeat_until_steps_cmplt` step, which
+/// points back to the first of them. The builder holds up to `N` steps,
+/// repeat steps included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkoutBuilder<'a, const N: usize> {
+    name: &'a str,
+    sport: FitSportType,
+    steps: Vec<Entry<'a>, N>,
+    overflow: bool,
+    empty_repeat: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Entry<'a> {
+    Step(WorkoutStep<'a>),
+    /// Repeat the steps from message index `from`, `times` times.
+    Repeat {
+        from: u16,
+        times: u32,
+    },
+}
+
+impl<'a> WorkoutStep<'a> {
+    pub fn new(
+        intensity: FitIntensityType,
+        duration: WorkoutDuration,
+        target: WorkoutTarget,
+    ) -> Self {
+        Self {
+            intensity,
+            duration,
+            target,
+            name: None,
+            notes: None,
+        }
+    }
+
+    pub fn warmup(duration: WorkoutDuration, target: WorkoutTarget) -> Self {
+        Self::new(FitIntensityType::Warmup, duration, target)
+    }
+
+    pub fn active(duration: WorkoutDuration, target: WorkoutTarget) -> Self {
+        Self::new(FitIntensityType::Active, duration, target)
+    }
+
+    pub fn recovery(duration: WorkoutDuration, target: WorkoutTarget) -> Self {
+        Self::new(FitIntensityType::Recovery, duration, target)
+    }
+
+    pub fn rest(duration: WorkoutDuration, target: WorkoutTarget) -> Self {
+        Self::new(FitIntensityType::Rest, duration, target)
+    }
+
+    pub fn cooldown(duration: WorkoutDuration, target: WorkoutTarget) -> Self {
+        Self::new(FitIntensityType::Cooldown, duration, target)
+    }
+
+    /// Name of the step, shown on the watch. Names are truncated to 254
+    /// bytes.
+    pub fn name(mut self, name: &'a str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Notes of the step. Notes are truncated to 254 bytes.
+    pub fn notes(mut self, notes: &'a str) -> Self {
+        self.notes = Some(notes);
+        self
+    }
+}
+
+impl<'a, const N: usize> WorkoutBuilder<'a, N> {
+    pub fn new(name: &'a str, sport: FitSportType) -> Self {
+        Self {
+            name,
+            sport,
+            steps: Vec::new(),
+            overflow: false,
+            empty_repeat: false,
+        }
+    }
+
+    pub fn step(&mut self, step: WorkoutStep<'a>) -> &mut Self {
+        self.push(Entry::Step(step))
+    }
+
+    pub fn warmup(&mut self, duration: WorkoutDuration, target: WorkoutTarget) -> &mut Self {
+        self.step(WorkoutStep::warmup(duration, target))
+    }
+
+    pub fn active(&mut self, duration: WorkoutDuration, target: WorkoutTarget) -> &mut Self {
+        self.step(WorkoutStep::active(duration, target))
+    }
+
+    pub fn recovery(&mut self, duration: WorkoutDuration, target: WorkoutTarget) -> &mut Self {
+        self.step(WorkoutStep::recovery(duration, target))
+    }
+
+    pub fn rest(&mut self, duration: WorkoutDuration, target: WorkoutTarget) -> &mut Self {
+        self.step(WorkoutStep::rest(duration, target))
+    }
+
+    pub fn cooldown(&mut self, duration: WorkoutDuration, target: WorkoutTarget) -> &mut Self {
+        self.step(WorkoutStep::cooldown(duration, target))
+    }
+
+    /// Do `steps` `times` times in a row.
+    pub fn repeat(&mut self, times: u32, steps: &[WorkoutStep<'a>]) -> &mut Self {
+        if times == 0 || steps.is_empty() {
+            self.empty_repeat = true;
+            return self;
+        }
+        let from = self.steps.len() as u16;
+        for step in steps {
+            self.step(*step);
+        }
+        self.push(Entry::Repeat { from, times })
+    }
+
+    /// Number of Workout Step messages, repeat steps included.
+    pub fn num_valid_steps(&self) -> u16 {
+        self.steps.len() as u16
+    }
+
+    /// Write the Workout message and its Workout Step messages.
+    ///
+    /// Fails with [`WorkoutError::TooManySteps`] if more than `N` steps
+    /// were added, and with [`WorkoutError::EmptyRepeat`] if a repeat had no
+    /// steps or was done no times.
+    pub fn write<S: FitSink>(&self, fit: &mut FitEncoder<S>) -> Result<(), WorkoutError> {
+        if self.overflow {
+            return Err(WorkoutError::TooManySteps);
+        }
+        if self.empty_repeat {
+            return Err(WorkoutError::EmptyRepeat);
+        }
+
+        let name = truncate(self.name, u8::MAX as usize - 1);
+        let workout = fit.define(
+            FitGlobalMessageType::Workout,
+            &[
+                FitWorkoutFieldDefinition::Sport.into(),
+                FitWorkoutFieldDefinition::NumValidSteps.into(),
+                FitWorkoutFieldDefinition::WktName.string(name.len() as u8 + 1)?,
+            ],
+        )?;
+        fit.push_values(
+            workout,
+            &[
+                (
+                    FitWorkoutFieldDefinition::Sport,
+                    FitValue::Enum(self.sport as u8),
+                ),
+                (
+                    FitWorkoutFieldDefinition::NumValidSteps,
+                    self.num_valid_steps().into(),
+                ),
+                (FitWorkoutFieldDefinition::WktName, name.into()),
+            ],
+        )?;
+
+        // Strings of all the steps are as long as the longest.
+        let len = |text: fn(&WorkoutStep<'a>) -> Option<&'a str>| {
+            self.steps
+                .iter()
+                .filter_map(|entry| match entry {
+                    Entry::Step(step) => text(step),
+                    Entry::Repeat { .. } => None,
+                })
+                .map(|text| truncate(text, u8::MAX as usize - 1).len() as u8 + 1)
+                .max()
+                .unwrap_or(1)
+        };
+        let workout_step = fit.define(
+            FitGlobalMessageType::WorkoutStep,
+            &[
+                FitWorkoutStepFieldDefinition::MessageIndex.into(),
+                FitWorkoutStepFieldDefinition::WktStepName.string(len(|step| step.name))?,
+                FitWorkoutStepFieldDefinition::DurationType.into(),
+                FitWorkoutStepFieldDefinition::DurationValue.into(),
+                FitWorkoutStepFieldDefinition::TargetType.into(),
+                FitWorkoutStepFieldDefinition::TargetValue.into(),
+                FitWorkoutStepFieldDefinition::CustomTargetValueLow.into(),
+                FitWorkoutStepFieldDefinition::CustomTargetValueHigh.into(),
+                FitWorkoutStepFieldDefinition::Intensity.into(),
+                FitWorkoutStepFieldDefinition::Notes.string(len(|step| step.notes))?,
+            ],
+        )?;
+
+        for (index, entry) in self.steps.iter().enumerate() {
+            let values = match entry {
+                Entry::Step(step) => step.values(index as u16),
+                Entry::Repeat { from, times } => repeat_values(index as u16, *from, *times),
+            };
+            fit.push_values(workout_step, &values)?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, entry: Entry<'a>) -> &mut Self {
+        if self.steps.push(entry).is_err() {
+            self.overflow = true;
+        }
+        self
+    }
+}
+
+type StepValues<'a> = [(FitWorkoutStepFieldDefinition, FitValue<'a>); 10];
+
+impl<'a> WorkoutStep<'a> {
+    fn values(&self, message_index: u16) -> StepValues<'a> {
+        use FitWorkoutStepFieldDefinition as Field;
+
+        let (duration_type, duration) = match self.duration {
+            WorkoutDuration::Open => (
+                FitWorkoutStepDuration::Open,
+                (
+                    Field::DurationValue,
+                    FitValue::Invalid(Field::DurationValue.base_type()),
+                ),
+            ),
+            WorkoutDuration::Time(seconds) => (
+                FitWorkoutStepDuration::Time,
+                stored_value(Field::DurationTime, Some(seconds)),
+            ),
+            WorkoutDuration::Distance(metres) => (
+                FitWorkoutStepDuration::Distance,
+                stored_value(Field::DurationDistance, Some(metres)),
+            ),
+            WorkoutDuration::Calories(calories) => (
+                FitWorkoutStepDuration::Calories,
+                (Field::DurationCalories, calories.into()),
+            ),
+            WorkoutDuration::HeartRateLessThan(hr) => (
+                FitWorkoutStepDuration::HrLessThan,
+                (Field::DurationHr, hr.value().into()),
+            ),
+            WorkoutDuration::HeartRateGreaterThan(hr) => (
+                FitWorkoutStepDuration::HrGreaterThan,
+                (Field::DurationHr, hr.value().into()),
+            ),
+            WorkoutDuration::PowerLessThan(power) => (
+                FitWorkoutStepDuration::PowerLessThan,
+                (Field::DurationPower, power.value().into()),
+            ),
+            WorkoutDuration::PowerGreaterThan(power) => (
+                FitWorkoutStepDuration::PowerGreaterThan,
+                (Field::DurationPower, power.value().into()),
+            ),
+        };
+
+        // Custom ranges have a zone of 0.
+        let invalid = |field: Field| (field, FitValue::Invalid(field.base_type()));
+        let (target_type, zone, low, high) = match self.target {
+            WorkoutTarget::Open => (
+                FitWorkoutStepTarget::Open,
+                (Field::TargetValue, 0u32.into()),
+                invalid(Field::CustomTargetValueLow),
+                invalid(Field::CustomTargetValueHigh),
+            ),
+            WorkoutTarget::HeartRateZone(zone) => (
+                FitWorkoutStepTarget::HeartRate,
+                (Field::TargetHrZone, (zone as u32).into()),
+                invalid(Field::CustomTargetValueLow),
+                invalid(Field::CustomTargetValueHigh),
+            ),
+            WorkoutTarget::HeartRate { low, high } => (
+                FitWorkoutStepTarget::HeartRate,
+                (Field::TargetHrZone, 0u32.into()),
+                (Field::CustomTargetHeartRateLow, low.value().into()),
+                (Field::CustomTargetHeartRateHigh, high.value().into()),
+            ),
+            WorkoutTarget::PowerZone(zone) => (
+                FitWorkoutStepTarget::Power,
+                (Field::TargetPowerZone, (zone as u32).into()),
+                invalid(Field::CustomTargetValueLow),
+                invalid(Field::CustomTargetValueHigh),
+            ),
+            WorkoutTarget::Power { low, high } => (
+                FitWorkoutStepTarget::Power,
+                (Field::TargetPowerZone, 0u32.into()),
+                (Field::CustomTargetPowerLow, low.value().into()),
+                (Field::CustomTargetPowerHigh, high.value().into()),
+            ),
+            WorkoutTarget::SpeedZone(zone) => (
+                FitWorkoutStepTarget::Speed,
+                (Field::TargetSpeedZone, (zone as u32).into()),
+                invalid(Field::CustomTargetValueLow),
+                invalid(Field::CustomTargetValueHigh),
+            ),
+            WorkoutTarget::Speed { low, high } => (
+                FitWorkoutStepTarget::Speed,
+                (Field::TargetSpeedZone, 0u32.into()),
+                stored_value(Field::CustomTargetSpeedLow, Some(low)),
+                stored_value(Field::CustomTargetSpeedHigh, Some(high)),
+            ),
+            WorkoutTarget::Cadence { low, high } => (
+                FitWorkoutStepTarget::Cadence,
+                (Field::TargetCadenceZone, 0u32.into()),
+                (Field::CustomTargetCadenceLow, low.into()),
+                (Field::CustomTargetCadenceHigh, high.into()),
+            ),
+        };
+
+        let text = |text: Option<&'a str>| text.map(|text| truncate(text, u8::MAX as usize - 1));
+        [
+            (Field::MessageIndex, message_index.into()),
+            (Field::WktStepName, text(self.name).into()),
+            (Field::DurationType, FitValue::Enum(duration_type as u8)),
+            duration,
+            (Field::TargetType, FitValue::Enum(target_type as u8)),
+            zone,
+            low,
+            high,
+            (Field::Intensity, FitValue::Enum(self.intensity as u8)),
+            (Field::Notes, text(self.notes).into()),
+        ]
+    }
+}
+
+impl From<FitError> for WorkoutError {
+    fn from(error: FitError) -> Self {
+        Self::Fit(error)
+    }
+}
+
+/// Values of a `repeat_until_steps_cmplt` step, repeating the steps from
+/// message index `from`.
+fn repeat_values<'a>(message_index: u16, from: u16, times: u32) -> StepValues<'a> {
+    use FitWorkoutStepFieldDefinition as Field;
+
+    let invalid = |field: Field| (field, FitValue::Invalid(field.base_type()));
+    [
+        (Field::MessageIndex, message_index.into()),
+        invalid(Field::WktStepName),
+        (
+            Field::DurationType,
+            FitValue::Enum(FitWorkoutStepDuration::RepeatUntilStepsCmplt as u8),
+        ),
+        (Field::DurationStep, (from as u32).into()),
+        (
+            Field::TargetType,
+            FitValue::Enum(FitWorkoutStepTarget::Open as u8),
+        ),
+        (Field::RepeatSteps, times.into()),
+        invalid(Field::CustomTargetValueLow),
+        invalid(Field::CustomTargetValueHigh),
+        invalid(Field::Intensity),
+        invalid(Field::Notes),
+    ]
+}
+
+impl WorkoutHeartRate {
+    /// Value of a `workout_hr` field, heart rates in bpm are offset by 100.
+    fn value(&self) -> u32 {
+        match self {
+            Self::Percent(percent) => *percent as u32,
+            Self::Bpm(bpm) => *bpm as u32 + 100,
+        }
+    }
+}
+
+impl WorkoutPower {
+    /// Value of a `workout_power` field, powers in watts are offset by 1000.
+    fn value(&self) -> u32 {
+        match self {
+            Self::Percent(percent) => *percent as u32,
+            Self::Watts(watts) => *watts as u32 + 1000,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+use fit_no_std::{
+    FitDateTime, FitFieldDefinition, FitFile, FitFileType, FitGlobalMessageType,
+    FitRecordFieldDefinition,
+};
+
+/// Runs fitdump on `data` from standard input.
+fn fitdump(data: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fitdump"))
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(data).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn activity() -> Vec<u8> {
+    let mut fit = FitFile::<128>::new(FitFileType::Activity, FitDateTime::new(0)).unwrap();
+    let record = fit
+        .define(
+            FitGlobalMessageType::Record,
+            &[
+                FitRecordFieldDefinition::Speed,
+                FitRecordFieldDefinition::Altitude,
+            ],
+        )
+        .unwrap();
+    fit.push_values(
+        record,
+        &[
+            (
+                FitRecordFieldDefinition::Speed,
+                FitRecordFieldDefinition::Speed.stored(3.3).unwrap(),
+            ),
+            (
+                FitRecordFieldDefinition::Altitude,
+                FitRecordFieldDefinition::Altitude.stored(12.0).unwrap(),
+            ),
+        ],
+    )
+    .unwrap();
+    fit.done().unwrap().to_vec()
+}
+
+#[test]
+fn dump_valid_file() {
+    let data = activity();
+    let output = fitdump(&data);
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(&format!("-: {} bytes\n", data.len())));
+    assert!(stdout.contains("profile 21.171"));
+    assert!(stdout.contains("definition local 1: record (20), LSB"));
+    // Physical values have as many decimals as their scale resolves.
+    assert!(stdout.contains("speed                        3300 (3.300 m/s)"));
+    assert!(stdout.contains("altitude                     2560 (12.0 m)"));
+    assert!(stdout.contains("file CRC"));
+    assert!(stdout.trim_end().ends_with("valid"));
+}
+
+#[test]
+fn dump_malformed_file() {
+    let mut data = activity();
+    *data.last_mut().unwrap() ^= 0xFF;
+    let output = fitdump(&data);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.trim_end().ends_with("invalid"));
+
+    let output = fitdump(b".FIT");
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("malformed header: InvalidHeader"));
+}
+
+#[test]
+fn dump_unreadable_file() {
+    let output = Command::new(env!("CARGO_BIN_EXE_fitdump"))
+        .arg("does-not-exist.fit")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("fitdump: does-not-exist.fit: "));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_fitdump"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}